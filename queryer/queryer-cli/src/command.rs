@@ -0,0 +1,130 @@
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use queryer::DataSet;
+
+/// REPL 中以 '.' 开头的元命令
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    /// 显示帮助信息
+    Help,
+    /// 退出 REPL
+    Quit,
+    /// 显示 url、catalog 中的表或者视图对应数据的 schema
+    Schema(String),
+    /// 列出 catalog 中注册的表以及会话中创建的视图
+    Tables,
+    /// 切换输出格式，不带参数时显示当前的输出格式
+    Format(Option<OutputFormat>),
+    /// 清空远程数据的本地缓存
+    Purge,
+}
+
+/// 查询结果的输出格式
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Csv,
+    Json,
+}
+
+pub const HELP: &str = "\
+.help               显示帮助信息
+.quit / .exit       退出
+.schema <url|name>  显示 url、catalog 中的表或者视图的列名和类型
+.tables             列出 catalog 中注册的表以及创建的视图
+.format [csv|table|json]
+                    切换输出格式，不带参数时显示当前格式
+.purge              清空远程数据的本地缓存
+
+其它输入都当作 SQL，以 ';' 结尾时执行，可以跨多行输入，
+CREATE VIEW 创建的视图在退出之前都可以使用，EXPLAIN <query> 显示执行计划";
+
+impl FromStr for Command {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().trim_end_matches(';');
+        let (name, arg) = match s.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, Some(arg.trim()).filter(|v| !v.is_empty())),
+            None => (s, None),
+        };
+
+        match (name, arg) {
+            (".help", None) => Ok(Command::Help),
+            (".quit" | ".exit", None) => Ok(Command::Quit),
+            (".schema", Some(url)) => Ok(Command::Schema(url.to_owned())),
+            (".schema", None) => Err(anyhow!("Usage: .schema <url|name>")),
+            (".tables", None) => Ok(Command::Tables),
+            (".purge", None) => Ok(Command::Purge),
+            (".format", arg) => Ok(Command::Format(arg.map(|v| v.parse()).transpose()?)),
+            _ => Err(anyhow!("Unknown command {}, try .help", s)),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "table" => Ok(OutputFormat::Table),
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            v => Err(anyhow!("Output format {} is not supported", v)),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// 按照输出格式渲染查询结果
+    pub fn render(&self, ds: &mut DataSet) -> Result<String> {
+        match self {
+            OutputFormat::Table => Ok(format!("{}", **ds)),
+            OutputFormat::Csv => Ok(ds.to_csv()?),
+            OutputFormat::Json => Ok(ds.to_json()?),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Table => "table",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Json => "json",
+        }
+    }
+}
+
+/// 把 schema 渲染成 `name: type` 的形式，每列一行
+pub fn render_schema<N: Display, T: Display>(fields: impl Iterator<Item = (N, T)>) -> String {
+    fields
+        .map(|(name, dtype)| format!("{}: {}", name, dtype))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_command_should_work() {
+        assert_eq!(".help".parse::<Command>().unwrap(), Command::Help);
+        assert_eq!(".exit;".parse::<Command>().unwrap(), Command::Quit);
+        assert_eq!(
+            ".schema  https://a.com/b.csv ".parse::<Command>().unwrap(),
+            Command::Schema("https://a.com/b.csv".into())
+        );
+        assert_eq!(
+            ".format CSV".parse::<Command>().unwrap(),
+            Command::Format(Some(OutputFormat::Csv))
+        );
+        assert_eq!(".format".parse::<Command>().unwrap(), Command::Format(None));
+        assert!(".format xml".parse::<Command>().is_err());
+        assert!(".schema".parse::<Command>().is_err());
+        assert_eq!(".purge".parse::<Command>().unwrap(), Command::Purge);
+        assert_eq!(".tables".parse::<Command>().unwrap(), Command::Tables);
+        assert!(".views".parse::<Command>().is_err());
+    }
+}
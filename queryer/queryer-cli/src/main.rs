@@ -0,0 +1,156 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use queryer::{cache, set_cache, Cache, Catalog, Session};
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+mod command;
+
+use command::{render_schema, Command, OutputFormat, HELP};
+
+const PROMPT: &str = "queryer> ";
+const CONTINUE_PROMPT: &str = "     ...> ";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // 默认只显示 10 行，交互式探索数据时显示多一些
+    if std::env::var_os("POLARS_FMT_MAX_ROWS").is_none() {
+        std::env::set_var("POLARS_FMT_MAX_ROWS", "50");
+    }
+
+    // 第一个参数是可选的 catalog 文件，注册过的表可以直接在 FROM 中使用
+    let catalog = match std::env::args().nth(1) {
+        Some(path) => Catalog::load(path)?,
+        None => Catalog::default(),
+    };
+    let mut session = Session::new(catalog);
+
+    // 交互式探索时反复查询同一个 url，开启磁盘缓存
+    set_cache(Some(Cache::default()));
+
+    let mut rl = DefaultEditor::new()?;
+    let history = history_file();
+    if let Some(path) = &history {
+        // 第一次运行时还没有历史文件，忽略错误即可
+        let _ = rl.load_history(path);
+    }
+
+    println!(
+        "queryer {}, enter .help for usage",
+        env!("CARGO_PKG_VERSION")
+    );
+
+    let mut format = OutputFormat::default();
+    // 多行输入的 SQL，遇到 ';' 结尾时才执行
+    let mut buf = String::new();
+
+    loop {
+        let prompt = if buf.is_empty() {
+            PROMPT
+        } else {
+            CONTINUE_PROMPT
+        };
+        let line = match rl.readline(prompt) {
+            Ok(line) => line,
+            // Ctrl-C 放弃当前输入，Ctrl-D 退出
+            Err(ReadlineError::Interrupted) => {
+                buf.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if buf.is_empty() && line.starts_with('.') {
+            rl.add_history_entry(line)?;
+            match line.parse() {
+                Ok(Command::Quit) => break,
+                Ok(cmd) => run_command(cmd, &session, &mut format).await,
+                Err(e) => eprintln!("{}", e),
+            }
+            continue;
+        }
+
+        if !buf.is_empty() {
+            buf.push('\n');
+        }
+        buf.push_str(line);
+        if !line.ends_with(';') {
+            continue;
+        }
+
+        let sql = std::mem::take(&mut buf);
+        rl.add_history_entry(&sql)?;
+        match session.query(sql.trim_end_matches(';')).await {
+            // CREATE VIEW 之类的语句没有结果
+            Ok(ds) if ds.width() == 0 => {}
+            // EXPLAIN 的结果直接按行输出，不画成表格
+            Ok(ds) if format == OutputFormat::Table && is_explain(&sql) => {
+                match ds["plan"].utf8() {
+                    Ok(plan) => plan
+                        .into_no_null_iter()
+                        .for_each(|line| println!("{}", line)),
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+            Ok(mut ds) => match format.render(&mut ds) {
+                Ok(output) => println!("{}", output),
+                Err(e) => eprintln!("{}", e),
+            },
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    if let Some(path) = &history {
+        rl.save_history(path)?;
+    }
+
+    Ok(())
+}
+
+// 执行元命令，出错时打印错误信息，不退出 REPL
+async fn run_command(cmd: Command, session: &Session, format: &mut OutputFormat) {
+    match cmd {
+        Command::Help => println!("{}", HELP),
+        Command::Format(None) => println!("{}", format.name()),
+        Command::Format(Some(f)) => *format = f,
+        Command::Schema(name) => {
+            let schema = match session.scan(&name).await {
+                Ok(df) => df.schema().map_err(|e| e.into()),
+                Err(e) => Err(e),
+            };
+            match schema {
+                Ok(schema) => println!("{}", render_schema(schema.iter())),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        Command::Tables => {
+            let mut names = session.catalog().names();
+            names.extend(session.views());
+            println!("{}", names.join("\n"));
+        }
+        Command::Purge => match cache() {
+            Some(cache) => match cache.purge().await {
+                Ok(()) => println!("cache {} purged", cache.dir().display()),
+                Err(e) => eprintln!("{}", e),
+            },
+            None => println!("cache is disabled"),
+        },
+        Command::Quit => {}
+    }
+}
+
+fn is_explain(sql: &str) -> bool {
+    let keyword = sql.split_whitespace().next().unwrap_or_default();
+    keyword.eq_ignore_ascii_case("explain")
+}
+
+// 历史记录保存在 $HOME/.queryer_history
+fn history_file() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".queryer_history"))
+}
@@ -0,0 +1,94 @@
+use napi::{bindgen_prelude::Buffer, Error, Result, Status};
+use napi_derive::napi;
+use queryer::{DataSet, QueryError, Session, Value};
+
+/// 执行 SQL，返回对象的数组，每一行是一个对象。params 绑定到 SQL 中的 `?` / `$1` 占位符
+#[napi]
+pub async fn query(
+    sql: String,
+    params: Option<Vec<serde_json::Value>>,
+) -> Result<Vec<serde_json::Value>> {
+    let mut ds = execute(sql, params).await?;
+    let rows = ds.to_json().map_err(js_err)?;
+    serde_json::from_str(&rows).map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// 和 query 一样，但返回 Arrow IPC 格式的 Buffer，可以直接交给 apache-arrow 的 `tableFromIPC`
+#[napi]
+pub async fn query_arrow(sql: String, params: Option<Vec<serde_json::Value>>) -> Result<Buffer> {
+    let mut ds = execute(sql, params).await?;
+    Ok(ds.to_ipc().map_err(js_err)?.into())
+}
+
+async fn execute(sql: String, params: Option<Vec<serde_json::Value>>) -> Result<DataSet> {
+    let params = params
+        .unwrap_or_default()
+        .iter()
+        .map(param)
+        .collect::<Result<Vec<_>>>()?;
+    Session::default()
+        .query_with_params(sql, &params)
+        .await
+        .map_err(js_err)
+}
+
+// js 中的参数转换成 queryer 的参数，只支持 null、boolean、number 和 string
+fn param(v: &serde_json::Value) -> Result<Value> {
+    match v {
+        serde_json::Value::Null => Ok(Value::Null),
+        serde_json::Value::Bool(b) => Ok(Value::Boolean(*b)),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(n) => Ok(Value::Int(n)),
+            None => Ok(Value::Float(n.as_f64().unwrap_or(f64::NAN))),
+        },
+        serde_json::Value::String(s) => Ok(Value::String(s.clone())),
+        v => Err(Error::new(
+            Status::InvalidArg,
+            format!("Parameter {} is not supported", v),
+        )),
+    }
+}
+
+// SQL 本身的问题在 js 中的 code 为 InvalidArg，其它错误为 GenericFailure
+fn js_err(e: QueryError) -> Error {
+    let status = match e {
+        QueryError::Parse { .. } | QueryError::Unsupported { .. } | QueryError::Invalid(_) => {
+            Status::InvalidArg
+        }
+        _ => Status::GenericFailure,
+    };
+    Error::new(status, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use napi::tokio::runtime::Runtime;
+
+    use super::*;
+
+    #[test]
+    fn query_should_return_rows() {
+        let sql = format!(
+            "SELECT location, new_cases FROM file://{}/../queryer/fixtures/covid.csv \
+            WHERE continent = ? ORDER BY location LIMIT 2",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let rt = Runtime::new().unwrap();
+
+        let rows = rt
+            .block_on(query(sql.clone(), Some(vec!["Europe".into()])))
+            .unwrap();
+        assert_eq!(
+            rows,
+            [
+                serde_json::json!({"location": "France", "new_cases": 19124}),
+                serde_json::json!({"location": "Germany", "new_cases": 10311}),
+            ]
+        );
+
+        let err = rt.block_on(query(sql.clone(), None)).unwrap_err();
+        assert_eq!(err.status, Status::InvalidArg);
+        let err = rt.block_on(query(sql, Some(vec![serde_json::json!([1])])));
+        assert_eq!(err.unwrap_err().status, Status::InvalidArg);
+    }
+}
@@ -0,0 +1,122 @@
+use std::sync::OnceLock;
+
+use pyo3::{
+    exceptions::{PyIOError, PyRuntimeError, PyValueError},
+    prelude::*,
+    types::PyBool,
+};
+use pyo3_polars::PyDataFrame;
+use queryer::{Catalog, QueryError, Session, Value};
+use tokio::runtime::Runtime;
+
+/// 绑定到 `?` / `$1` 占位符的参数，支持 None、bool、int、float、str
+struct Param(Value);
+
+impl<'source> FromPyObject<'source> for Param {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        // bool 是 int 的子类，要先判断
+        let value = if ob.is_none() {
+            Value::Null
+        } else if ob.is_instance_of::<PyBool>() {
+            Value::Boolean(ob.extract()?)
+        } else if let Ok(n) = ob.extract::<i64>() {
+            Value::Int(n)
+        } else if let Ok(n) = ob.extract::<f64>() {
+            Value::Float(n)
+        } else {
+            Value::String(ob.extract()?)
+        };
+        Ok(Param(value))
+    }
+}
+
+/// query(sql, params=None, catalog=None)
+/// --
+///
+/// 执行 SQL，返回 polars.DataFrame。params 绑定到 SQL 中的 `?` / `$1` 占位符，
+/// catalog 是可选的 catalog 文件，注册过的表可以直接在 FROM 中使用
+#[pyfunction]
+#[pyo3(signature = (sql, params = None, catalog = None))]
+fn query(
+    py: Python<'_>,
+    sql: String,
+    params: Option<Vec<Param>>,
+    catalog: Option<String>,
+) -> PyResult<PyDataFrame> {
+    let params: Vec<_> = params
+        .unwrap_or_default()
+        .into_iter()
+        .map(|p| p.0)
+        .collect();
+    // 查询时释放 GIL，其它 python 线程可以继续执行
+    let result = py.allow_threads(|| {
+        let catalog = match catalog {
+            Some(path) => Catalog::load(path)?,
+            None => Catalog::default(),
+        };
+        runtime().block_on(Session::new(catalog).query_with_params(sql, &params))
+    });
+    match result {
+        Ok(ds) => Ok(PyDataFrame(ds.into())),
+        Err(e) => Err(py_err(e)),
+    }
+}
+
+// SQL 本身的问题抛出 ValueError，获取数据失败抛出 IOError，其它的抛出 RuntimeError
+fn py_err(e: QueryError) -> PyErr {
+    let message = e.to_string();
+    match e {
+        QueryError::Parse { .. } | QueryError::Unsupported { .. } | QueryError::Invalid(_) => {
+            PyValueError::new_err(message)
+        }
+        QueryError::Fetch { .. } | QueryError::Io(_) => PyIOError::new_err(message),
+        _ => PyRuntimeError::new_err(message),
+    }
+}
+
+// 所有的查询共用一个 tokio runtime
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| Runtime::new().expect("failed to create tokio runtime"))
+}
+
+/// 在 python 中通过 SQL 查询 url 中的数据
+#[pymodule]
+#[pyo3(name = "queryer")]
+fn queryer_py(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(query, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn param_should_extract_python_values() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let params: Vec<Param> = py
+                .eval("[None, True, 1, 1.5, 'a']", None, None)
+                .unwrap()
+                .extract()
+                .unwrap();
+            let params: Vec<_> = params.into_iter().map(|p| p.0).collect();
+            assert_eq!(
+                params,
+                [
+                    Value::Null,
+                    Value::Boolean(true),
+                    Value::Int(1),
+                    Value::Float(1.5),
+                    Value::String("a".into())
+                ]
+            );
+            assert!(py
+                .eval("[[1]]", None, None)
+                .unwrap()
+                .extract::<Vec<Param>>()
+                .is_err());
+        });
+    }
+}
@@ -0,0 +1,406 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    http::{
+        header::{ACCEPT, CONTENT_TYPE},
+        HeaderMap, HeaderValue, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    routing::post,
+    Extension, Json, Router,
+};
+use queryer::{Catalog, DataSet, QueryError, Session, Value};
+use serde::Deserialize;
+use serde_json::json;
+use tower_http::trace::TraceLayer;
+use tracing::warn;
+
+/// 结果被截断时设置的响应头
+const TRUNCATED: &str = "x-queryer-truncated";
+
+/// 服务的配置
+pub struct Config {
+    /// FROM 中的表名先在 catalog 中查找
+    pub catalog: Catalog,
+    /// 单个查询的超时时间
+    pub timeout: Duration,
+    /// 返回的最大行数，超过时截断
+    pub max_rows: usize,
+    /// 是否流式执行，见 `Session::with_streaming`
+    pub streaming: bool,
+    /// 是否允许在 FROM 中直接使用 http / https 的 url，默认只能查询 catalog 中的表
+    pub allow_urls: bool,
+    /// 是否允许在 FROM 中直接使用 file:// 读取服务器上的文件
+    pub allow_files: bool,
+}
+
+/// POST /query 的请求：`{"sql": "SELECT ... WHERE a = ?", "params": [1]}`
+#[derive(Debug, Deserialize)]
+pub struct QueryRequest {
+    sql: String,
+    // 绑定到 `?` / `$1` 占位符的参数
+    #[serde(default)]
+    params: Vec<serde_json::Value>,
+}
+
+/// 根据 Accept 选择的返回格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+    Csv,
+    Arrow,
+}
+
+/// 请求出错时返回的错误，body 为 `{"error": "..."}`
+#[derive(Debug)]
+enum ApiError {
+    Query(QueryError),
+    BadRequest(String),
+    NotAcceptable,
+    Timeout,
+    Internal(String),
+}
+
+pub fn router(config: Config) -> Router {
+    Router::new()
+        .route("/query", post(query))
+        .layer(Extension(Arc::new(config)))
+        .layer(TraceLayer::new_for_http())
+}
+
+async fn query(
+    Extension(config): Extension<Arc<Config>>,
+    headers: HeaderMap,
+    Json(req): Json<QueryRequest>,
+) -> Result<Response, ApiError> {
+    let accept = headers.get(ACCEPT).and_then(|v| v.to_str().ok());
+    let format = Format::negotiate(accept).ok_or(ApiError::NotAcceptable)?;
+    let params = req
+        .params
+        .iter()
+        .map(param)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // 查询放在单独的任务中执行，超时后取消任务，停止还在进行的下载和解析。
+    // 最后在阻塞线程池中执行的 collect 无法中途取消，会在后台继续执行完。
+    // 多取一行用来判断结果是否被截断
+    let mut session = Session::new(config.catalog.clone())
+        .with_streaming(config.streaming)
+        .with_allowed_schemes(config.schemes())
+        .with_max_rows(config.max_rows.saturating_add(1));
+    let mut task = tokio::spawn(async move { session.query_with_params(req.sql, &params).await });
+    let mut ds = match tokio::time::timeout(config.timeout, &mut task).await {
+        Ok(Ok(result)) => result?,
+        Ok(Err(e)) => return Err(ApiError::Internal(e.to_string())),
+        Err(_) => {
+            task.abort();
+            return Err(ApiError::Timeout);
+        }
+    };
+
+    let mut headers = HeaderMap::new();
+    if ds.height() > config.max_rows {
+        *ds = ds.head(Some(config.max_rows));
+        headers.insert(TRUNCATED, HeaderValue::from_static("true"));
+    }
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static(format.content_type()),
+    );
+    Ok((headers, format.render(&mut ds)?).into_response())
+}
+
+// JSON 中的参数转换成 queryer 的参数，只支持标量
+fn param(v: &serde_json::Value) -> Result<Value, ApiError> {
+    match v {
+        serde_json::Value::Null => Ok(Value::Null),
+        serde_json::Value::Bool(b) => Ok(Value::Boolean(*b)),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(n) => Ok(Value::Int(n)),
+            None => Ok(Value::Float(n.as_f64().unwrap_or(f64::NAN))),
+        },
+        serde_json::Value::String(s) => Ok(Value::String(s.clone())),
+        v => Err(ApiError::BadRequest(format!(
+            "Parameter {} is not supported",
+            v
+        ))),
+    }
+}
+
+impl Config {
+    // FROM 中可以直接使用的 url scheme
+    fn schemes(&self) -> Vec<&'static str> {
+        let mut schemes = Vec::new();
+        if self.allow_urls {
+            schemes.extend(["http", "https"]);
+        }
+        if self.allow_files {
+            schemes.push("file");
+        }
+        schemes
+    }
+}
+
+impl Format {
+    /// 按 Accept 中的 q 值从高到低选择支持的格式，没有 Accept 时返回 JSON
+    fn negotiate(accept: Option<&str>) -> Option<Format> {
+        let accept = match accept.map(str::trim) {
+            None | Some("") => return Some(Format::Json),
+            Some(accept) => accept,
+        };
+
+        let mut ranges: Vec<(&str, f32)> = accept
+            .split(',')
+            .map(|range| {
+                let mut parts = range.split(';').map(str::trim);
+                let media = parts.next().unwrap_or_default();
+                let q = parts
+                    .find_map(|p| p.strip_prefix("q="))
+                    .and_then(|q| q.parse().ok())
+                    .unwrap_or(1.0);
+                (media, q)
+            })
+            .filter(|(_, q)| *q > 0.0)
+            .collect();
+        // sort_by 是稳定排序，q 值相同时保持原来的顺序
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        ranges
+            .into_iter()
+            .find_map(|(media, _)| match media.to_ascii_lowercase().as_str() {
+                "application/json" | "application/*" | "*/*" => Some(Format::Json),
+                "text/csv" | "text/*" => Some(Format::Csv),
+                "application/vnd.apache.arrow.file" => Some(Format::Arrow),
+                _ => None,
+            })
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Csv => "text/csv",
+            Format::Arrow => "application/vnd.apache.arrow.file",
+        }
+    }
+
+    fn render(&self, ds: &mut DataSet) -> Result<Vec<u8>, QueryError> {
+        match self {
+            Format::Json => Ok(ds.to_json()?.into_bytes()),
+            Format::Csv => Ok(ds.to_csv()?.into_bytes()),
+            Format::Arrow => ds.to_ipc(),
+        }
+    }
+}
+
+impl From<QueryError> for ApiError {
+    fn from(e: QueryError) -> Self {
+        ApiError::Query(e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            ApiError::Query(e) => status(e),
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotAcceptable => StatusCode::NOT_ACCEPTABLE,
+            ApiError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let message = match self {
+            ApiError::Query(e) => e.to_string(),
+            ApiError::BadRequest(message) | ApiError::Internal(message) => message,
+            ApiError::NotAcceptable => "Supported formats: application/json, text/csv, \
+                application/vnd.apache.arrow.file"
+                .to_owned(),
+            ApiError::Timeout => "Query timed out".to_owned(),
+        };
+        if status.is_server_error() {
+            warn!("query failed: {}", message);
+        }
+        (status, Json(json!({ "error": message }))).into_response()
+    }
+}
+
+// SQL 本身的问题是客户端错误，获取数据失败是上游的错误
+fn status(e: &QueryError) -> StatusCode {
+    match e {
+        QueryError::Parse { .. } | QueryError::Unsupported { .. } | QueryError::Invalid(_) => {
+            StatusCode::BAD_REQUEST
+        }
+        QueryError::Fetch { .. } => StatusCode::BAD_GATEWAY,
+        QueryError::Execution(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use queryer::TableSource;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn config(max_rows: usize) -> Config {
+        let url = format!(
+            "file://{}/../queryer/fixtures/covid.csv",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        Config {
+            catalog: Catalog::new().with_table("covid", TableSource::new(url)),
+            timeout: Duration::from_secs(10),
+            max_rows,
+            streaming: false,
+            allow_urls: false,
+            allow_files: false,
+        }
+    }
+
+    fn app(max_rows: usize) -> Router {
+        router(config(max_rows))
+    }
+
+    async fn post(app: Router, accept: &str, body: serde_json::Value) -> Response {
+        let req = Request::post("/query")
+            .header(CONTENT_TYPE, "application/json")
+            .header(ACCEPT, accept)
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        app.oneshot(req).await.unwrap()
+    }
+
+    async fn body(resp: Response) -> Vec<u8> {
+        hyper::body::to_bytes(resp.into_body())
+            .await
+            .unwrap()
+            .to_vec()
+    }
+
+    #[test]
+    fn negotiate_should_work() {
+        assert_eq!(Format::negotiate(None), Some(Format::Json));
+        assert_eq!(Format::negotiate(Some("text/csv")), Some(Format::Csv));
+        assert_eq!(
+            Format::negotiate(Some("text/csv;q=0.5, application/vnd.apache.arrow.file")),
+            Some(Format::Arrow)
+        );
+        assert_eq!(
+            Format::negotiate(Some("image/png, */*;q=0.1")),
+            Some(Format::Json)
+        );
+        assert_eq!(Format::negotiate(Some("image/png, text/csv;q=0")), None);
+    }
+
+    #[tokio::test]
+    async fn query_should_negotiate_format() {
+        let sql = json!({
+            "sql": "SELECT location, new_cases FROM covid WHERE continent = ? ORDER BY location",
+            "params": ["Europe"],
+        });
+
+        let resp = post(app(100), "application/json", sql.clone()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let rows: Vec<serde_json::Value> = serde_json::from_slice(&body(resp).await).unwrap();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0]["location"], "France");
+
+        let resp = post(app(2), "text/csv", sql.clone()).await;
+        assert_eq!(resp.headers()[CONTENT_TYPE], "text/csv");
+        assert_eq!(resp.headers()[TRUNCATED], "true");
+        let csv = String::from_utf8(body(resp).await).unwrap();
+        assert_eq!(
+            csv.lines().collect::<Vec<_>>()[..2],
+            ["location,new_cases", "France,19124"]
+        );
+        assert_eq!(csv.lines().count(), 3);
+
+        let resp = post(app(100), "application/vnd.apache.arrow.file", sql.clone()).await;
+        assert!(resp.headers().get(TRUNCATED).is_none());
+        assert!(body(resp).await.starts_with(b"ARROW1"));
+
+        let resp = post(app(100), "image/png", sql).await;
+        assert_eq!(resp.status(), StatusCode::NOT_ACCEPTABLE);
+    }
+
+    #[tokio::test]
+    async fn query_errors_should_map_to_status() {
+        let cases = [
+            (
+                json!({ "sql": "SELECT FROM WHERE" }),
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                json!({ "sql": "DELETE FROM covid" }),
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                json!({ "sql": "SELECT a FROM covid WHERE a = ?", "params": [[1]] }),
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                json!({ "sql": "SELECT unknown FROM covid" }),
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+        ];
+        for (sql, status) in cases {
+            let resp = post(app(100), "*/*", sql).await;
+            assert_eq!(resp.status(), status);
+            let error: serde_json::Value = serde_json::from_slice(&body(resp).await).unwrap();
+            assert!(error["error"].is_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn urls_should_be_rejected_by_default() {
+        for sql in [
+            "SELECT * FROM file:///etc/passwd",
+            "DESCRIBE file:///etc/passwd",
+            "SELECT * FROM http://127.0.0.1:1/data.csv",
+            "SELECT location FROM covid WHERE location IN (SELECT a FROM file:///etc/passwd)",
+        ] {
+            let resp = post(app(100), "*/*", json!({ "sql": sql })).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{}", sql);
+        }
+
+        // 允许读取文件之后，文件不存在是获取数据失败
+        let app = router(Config {
+            allow_files: true,
+            ..config(100)
+        });
+        let sql = json!({ "sql": "SELECT * FROM file:///not/exist.csv" });
+        assert_eq!(
+            post(app, "*/*", sql).await.status(),
+            StatusCode::BAD_GATEWAY
+        );
+    }
+
+    #[tokio::test]
+    async fn timeout_should_cancel_query() {
+        use tokio::{io::AsyncReadExt, net::TcpListener, sync::oneshot};
+
+        // 只接收请求、不返回响应的 http server，连接被关闭时通知测试
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            while socket.read(&mut buf).await.unwrap_or(0) > 0 {}
+            let _ = tx.send(());
+        });
+
+        let app = router(Config {
+            timeout: Duration::from_millis(100),
+            allow_urls: true,
+            ..config(100)
+        });
+        let sql = json!({ "sql": format!("SELECT * FROM http://{}/data.csv", addr) });
+        let resp = post(app, "*/*", sql).await;
+        assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+
+        // 超时之后下载被取消，连接随之关闭
+        let closed = tokio::time::timeout(Duration::from_secs(5), rx).await;
+        assert!(matches!(closed, Ok(Ok(()))));
+    }
+}
@@ -0,0 +1,63 @@
+use std::{net::SocketAddr, time::Duration};
+
+use anyhow::Result;
+use queryer::{set_cache, Cache, Catalog};
+
+mod handler;
+
+use handler::{router, Config};
+
+// 默认的监听地址、查询超时时间（秒）、返回的最大行数以及缓存的有效期（秒）
+const DEFAULT_ADDR: &str = "127.0.0.1:3000";
+const DEFAULT_TIMEOUT: u64 = 30;
+const DEFAULT_MAX_ROWS: usize = 10_000;
+const DEFAULT_CACHE_TTL: u64 = 0;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // 初始化 tracing
+    tracing_subscriber::fmt::init();
+
+    // 第一个参数是可选的 catalog 文件，和 queryer-cli 一样
+    let catalog = match std::env::args().nth(1) {
+        Some(path) => Catalog::load(path)?,
+        None => Catalog::default(),
+    };
+    let config = Config {
+        catalog,
+        timeout: Duration::from_secs(env_or("QUERYER_TIMEOUT", DEFAULT_TIMEOUT)?),
+        max_rows: env_or("QUERYER_MAX_ROWS", DEFAULT_MAX_ROWS)?,
+        streaming: env_or("QUERYER_STREAMING", false)?,
+        allow_urls: env_or("QUERYER_ALLOW_URLS", false)?,
+        allow_files: env_or("QUERYER_ALLOW_FILES", false)?,
+    };
+    // 开启磁盘缓存，默认每次查询都向服务器重新验证，数据没有变化时直接读取缓存中解析好的 parquet
+    if env_or("QUERYER_CACHE", true)? {
+        let ttl = env_or("QUERYER_CACHE_TTL", DEFAULT_CACHE_TTL)?;
+        set_cache(Some(Cache::default().with_ttl(Duration::from_secs(ttl))));
+    }
+    let addr: SocketAddr = match std::env::var("QUERYER_ADDR") {
+        Ok(addr) => addr.parse()?,
+        Err(_) => DEFAULT_ADDR.parse()?,
+    };
+
+    tracing::info!("listening on {}", addr);
+
+    axum::Server::bind(&addr)
+        .serve(router(config).into_make_service())
+        .await?;
+
+    Ok(())
+}
+
+// 从环境变量中读取配置，没有设置时使用默认值
+fn env_or<T>(name: &str, default: T) -> Result<T>
+where
+    T: std::str::FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match std::env::var(name) {
+        Ok(v) => Ok(v.parse()?),
+        Err(_) => Ok(default),
+    }
+}
@@ -1,24 +1,14 @@
-use std::io::Cursor;
-
-use anyhow::Result;
-use polars::{prelude::{CsvReader, SerReader}, series::ChunkCompare};
+use queryer::{example_sql, query, Result};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
 
-    let url = "https://raw.githubusercontent.com/owid/covid-19-data/master/public/data/latest/owid-covid-latest.csv";
-    let data = reqwest::get(url).await?.text().await?;
-
-    // 使用 polars 直接请求
-    let df = CsvReader::new(Cursor::new(data))
-        .infer_schema(Some(16))
-        .finish()?;
-
-    let mask = df["location"].equal("China")?;
-    let filtered = df.filter(&mask)?;
-    println!("{:?}", filtered.select(["location", "total_cases", "new_cases", "total_deaths", "new_deaths"])?);
+    let sql = example_sql();
 
+    // 使用 queryer 直接查询 url 上的 csv
+    let df = query(sql).await?;
+    println!("{:?}", df);
 
     Ok(())
-}
\ No newline at end of file
+}
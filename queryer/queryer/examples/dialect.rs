@@ -5,6 +5,6 @@ fn main() {
 
     let sql = "SELECT * FROM tbl where id = 1";
     
-    let ast = Parser::parse_sql(&GenericDialect::default(), sql).unwrap();
+    let ast = Parser::parse_sql(&GenericDialect {}, sql).unwrap();
     println!("{:#?}", ast);
 }
\ No newline at end of file
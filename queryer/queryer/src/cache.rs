@@ -0,0 +1,455 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+use tokio::fs;
+use tracing::warn;
+
+use crate::{
+    blocking,
+    catalog::TableSource,
+    error::{invalid, Result},
+    fetcher::{CachedFetcher, Content, Revalidated},
+};
+
+// 缓存在 TTL 之内直接使用，不再向服务器确认
+const DEFAULT_TTL: Duration = Duration::from_secs(3600);
+
+/// 远程数据在本地磁盘上的缓存，以 url 为 key
+///
+/// 每个 url 对应三个文件：`<key>.data` 保存原始数据，`<key>.meta` 保存 ETag / Last-Modified 等信息，
+/// `<key>-<variant>.parquet` 保存按照不同格式和选项解析之后的 DataFrame，之后的查询不需要再解析原始数据和推断类型
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+/// 缓存条目的元信息，用于判断是否过期以及向服务器重新验证
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub(crate) struct Meta {
+    pub(crate) url: String,
+    pub(crate) etag: Option<String>,
+    pub(crate) last_modified: Option<String>,
+    pub(crate) content_type: Option<String>,
+    pub(crate) content_encoding: Option<String>,
+    // 上一次从服务器获取或者验证的时间（unix 时间戳，秒）
+    pub(crate) fetched_at: u64,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self::new(Self::default_dir())
+    }
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl: DEFAULT_TTL,
+        }
+    }
+
+    /// 设置缓存的有效期，为 0 时每次都会向服务器重新验证
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// 缓存目录：$QUERYER_CACHE_DIR > $XDG_CACHE_HOME/queryer > $HOME/.cache/queryer > 临时目录
+    pub fn default_dir() -> PathBuf {
+        let env = |name| std::env::var_os(name).map(PathBuf::from);
+        env("QUERYER_CACHE_DIR")
+            .or_else(|| env("XDG_CACHE_HOME").map(|p| p.join("queryer")))
+            .or_else(|| env("HOME").map(|p| p.join(".cache").join("queryer")))
+            .unwrap_or_else(|| std::env::temp_dir().join("queryer"))
+    }
+
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    /// 删除某个 url 的缓存
+    pub async fn remove(&self, url: &str) -> Result<()> {
+        for path in [self.meta_path(url), self.data_path(url)] {
+            if fs::try_exists(&path).await? {
+                fs::remove_file(path).await?;
+            }
+        }
+        self.remove_parquet(url).await
+    }
+
+    /// 删除缓存目录中所有的缓存文件
+    ///
+    /// 目录可能是用户指定的，所以只删除文件名符合缓存格式的文件，不会删除目录本身和其它文件
+    pub async fn purge(&self) -> Result<()> {
+        if !fs::try_exists(&self.dir).await? {
+            return Ok(());
+        }
+        let mut entries = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if is_cache_file(&entry.file_name().to_string_lossy())
+                && entry.file_type().await?.is_file()
+            {
+                fs::remove_file(entry.path()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 读取 url 对应缓存的元信息，不存在或者损坏时返回 None
+    pub(crate) async fn get(&self, url: &str) -> Option<Meta> {
+        let meta = fs::read(self.meta_path(url)).await.ok()?;
+        let meta: Meta = serde_json::from_slice(&meta).ok()?;
+        // key 是 url 的 hash，需要确认不是别的 url
+        if meta.url != url || !fs::try_exists(self.data_path(url)).await.ok()? {
+            return None;
+        }
+        Some(meta)
+    }
+
+    /// 读取缓存的原始数据
+    pub(crate) async fn content(&self, meta: Meta) -> Result<Content> {
+        Ok(Content {
+            data: fs::read(self.data_path(&meta.url)).await?,
+            content_type: meta.content_type,
+            content_encoding: meta.content_encoding,
+        })
+    }
+
+    /// 写入缓存，先写数据再写元信息，这样元信息存在时数据一定是完整的
+    ///
+    /// 覆盖已有的缓存时，数据和元信息都先写到临时文件再重命名，并发读取的查询不会读到写了一半的文件。
+    /// 原始数据变了，之前解析出来的 parquet 也就失效了，需要删掉
+    pub(crate) async fn put(&self, meta: &Meta, data: &[u8]) -> Result<()> {
+        fs::create_dir_all(&self.dir).await?;
+        self.remove_parquet(&meta.url).await?;
+        write_file(&self.data_path(&meta.url), data).await?;
+        self.put_meta(meta).await
+    }
+
+    /// 只更新元信息，比如服务器返回 304 之后刷新获取时间
+    pub(crate) async fn put_meta(&self, meta: &Meta) -> Result<()> {
+        fs::create_dir_all(&self.dir).await?;
+        write_file(&self.meta_path(&meta.url), &serde_json::to_vec(meta)?).await
+    }
+
+    /// 缓存是否还在有效期之内
+    pub(crate) fn is_fresh(&self, meta: &Meta) -> bool {
+        now().saturating_sub(meta.fetched_at) < self.ttl.as_secs()
+    }
+
+    /// 获取 table 对应的数据
+    ///
+    /// 解析之后的数据以 parquet 格式保存在缓存中，缓存有效时直接用 scan_parquet 读取，
+    /// 这样可以跳过解析和类型推断，并且可以利用 polars 的谓词下推和投影下推
+    pub(crate) async fn scan(&self, table: &TableSource) -> Result<LazyFrame> {
+        let url = table.url.as_str();
+        let fetcher = CachedFetcher { url, cache: self };
+        let path = self.parquet_path(table);
+        let content = match fetcher.revalidate().await? {
+            Revalidated::Cached(_) if fs::try_exists(&path).await? => return scan_parquet(&path),
+            Revalidated::Cached(meta) => self.content(meta).await?,
+            Revalidated::Fetched(content) => content,
+        };
+
+        // 解析和写入 parquet 都很耗时，放到阻塞线程池中执行
+        let table = table.clone();
+        blocking(move || {
+            let mut ds = table.loader(content)?.load()?;
+            match put_parquet(&path, &mut ds) {
+                Ok(()) => scan_parquet(&path),
+                Err(e) => {
+                    warn!("failed to cache {} as parquet: {}", table.url, e);
+                    Ok(ds.0.lazy())
+                }
+            }
+        })
+        .await
+    }
+
+    // 删除 url 对应的所有 parquet 文件
+    async fn remove_parquet(&self, url: &str) -> Result<()> {
+        if !fs::try_exists(&self.dir).await? {
+            return Ok(());
+        }
+        let prefix = format!("{}-", key(url));
+        let mut entries = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with(&prefix) && name.ends_with(".parquet") {
+                fs::remove_file(entry.path()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    fn meta_path(&self, url: &str) -> PathBuf {
+        self.dir.join(format!("{}.meta", key(url)))
+    }
+
+    fn data_path(&self, url: &str) -> PathBuf {
+        self.dir.join(format!("{}.data", key(url)))
+    }
+
+    // 同一个 url 按照不同的格式和选项解析出来的结果不同，需要分开保存
+    fn parquet_path(&self, table: &TableSource) -> PathBuf {
+        let variant = format!("{:?}{:?}", table.format, table.options);
+        self.dir
+            .join(format!("{}-{}.parquet", key(&table.url), key(&variant)))
+    }
+}
+
+// 先写到临时文件再重命名，避免并发的查询读到写了一半的文件
+fn put_parquet(path: &Path, df: &mut DataFrame) -> Result<()> {
+    let mut tmp = temp_file(path)?;
+    ParquetWriter::new(tmp.as_file_mut()).finish(df)?;
+    tmp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+// 先写到临时文件再重命名，覆盖已有的文件时不会被读到写了一半的内容
+async fn write_file(path: &Path, data: &[u8]) -> Result<()> {
+    let tmp = temp_file(path)?;
+    fs::write(tmp.path(), data).await?;
+    tmp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+// 在 path 所在的目录中创建 `<文件名>.<随机字符>.tmp`，每次写入用不同的临时文件，
+// 并发写入同一个缓存文件时不会互相覆盖
+fn temp_file(path: &Path) -> Result<NamedTempFile> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    Ok(tempfile::Builder::new()
+        .prefix(&format!("{}.", name))
+        .suffix(".tmp")
+        .tempfile_in(dir)?)
+}
+
+fn scan_parquet(path: &Path) -> Result<LazyFrame> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| invalid!("Invalid cache path {}", path.display()))?;
+    Ok(LazyFrame::scan_parquet(path, ScanArgsParquet::default())?)
+}
+
+/// 当前的 unix 时间戳（秒）
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+// 用 FNV-1a 计算 url 的 hash 作为文件名，结果在不同的 rust 版本之间保持稳定
+fn key(url: &str) -> String {
+    let hash = url.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+// 文件名是否是 `<key>.meta`、`<key>.data`、`<key>-<variant>.parquet` 或者写了一半的 `<缓存文件>.<随机字符>.tmp`
+fn is_cache_file(name: &str) -> bool {
+    if let Some((name, _)) = name.strip_suffix(".tmp").and_then(|v| v.rsplit_once('.')) {
+        return is_cache_file(name);
+    }
+    let is_key = |v: &str| v.len() == 16 && v.bytes().all(|b| b.is_ascii_hexdigit());
+    if let Some(key) = name
+        .strip_suffix(".meta")
+        .or_else(|| name.strip_suffix(".data"))
+    {
+        return is_key(key);
+    }
+    match name
+        .strip_suffix(".parquet")
+        .and_then(|v| v.split_once('-'))
+    {
+        Some((key, variant)) => is_key(key) && is_key(variant),
+        None => false,
+    }
+}
+
+// 缓存默认是关闭的，需要调用 set_cache 开启
+fn global() -> &'static RwLock<Option<Arc<Cache>>> {
+    static CACHE: OnceLock<RwLock<Option<Arc<Cache>>>> = OnceLock::new();
+    CACHE.get_or_init(|| RwLock::new(None))
+}
+
+/// 设置 http / https 数据源使用的缓存，传入 None 时关闭缓存（默认是关闭的）
+pub fn set_cache(cache: Option<Cache>) {
+    *global().write().unwrap() = cache.map(Arc::new);
+}
+
+/// 获取当前使用的缓存
+pub fn cache() -> Option<Arc<Cache>> {
+    global().read().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LoadOptions;
+
+    fn meta(url: &str, fetched_at: u64) -> Meta {
+        Meta {
+            url: url.into(),
+            etag: Some("\"v1\"".into()),
+            content_type: Some("text/csv".into()),
+            fetched_at,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn cache_put_get_remove_should_work() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path());
+        let url = "https://a.com/b.csv";
+
+        assert!(cache.get(url).await.is_none());
+        cache.put(&meta(url, now()), b"a,b\n1,2\n").await.unwrap();
+
+        let meta = cache.get(url).await.unwrap();
+        assert_eq!(meta.etag.as_deref(), Some("\"v1\""));
+        let content = cache.content(meta).await.unwrap();
+        assert_eq!(content.data, b"a,b\n1,2\n");
+        assert_eq!(content.content_type.as_deref(), Some("text/csv"));
+        assert!(cache.get("https://a.com/c.csv").await.is_none());
+
+        cache.remove(url).await.unwrap();
+        assert!(cache.get(url).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn cache_put_should_replace_existing_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path());
+        let url = "https://a.com/b.csv";
+        cache.put(&meta(url, now()), b"a,b\n1,2\n").await.unwrap();
+
+        // 覆盖已有的缓存，临时文件在写完之后被重命名，不会留在目录中
+        cache.put(&meta(url, now()), b"a,b\n3,4\n").await.unwrap();
+        let content = cache.content(cache.get(url).await.unwrap()).await.unwrap();
+        assert_eq!(content.data, b"a,b\n3,4\n");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn cache_purge_should_only_remove_cache_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path());
+        let url = "https://a.com/b.csv";
+        let table = TableSource::new(url);
+        cache.put(&meta(url, now()), b"a\n1\n").await.unwrap();
+        cache.scan(&table).await.unwrap().collect().unwrap();
+        assert!(cache.parquet_path(&table).exists());
+
+        // 缓存目录可能是用户的其它目录，其它文件和子目录都要保留
+        let other = dir.path().join("notes.data");
+        std::fs::write(&other, b"keep").unwrap();
+        std::fs::create_dir(dir.path().join("0123456789abcdef.data")).unwrap();
+
+        cache.purge().await.unwrap();
+        assert!(cache.get(url).await.is_none());
+        assert!(!cache.parquet_path(&table).exists());
+        assert!(other.exists());
+        assert!(dir.path().join("0123456789abcdef.data").is_dir());
+    }
+
+    #[test]
+    fn cache_should_be_disabled_by_default() {
+        assert!(cache().is_none());
+        assert!(is_cache_file("0123456789abcdef.meta"));
+        assert!(is_cache_file("0123456789abcdef.data.a1B2c3.tmp"));
+        assert!(is_cache_file("0123456789abcdef-fedcba9876543210.parquet"));
+        assert!(is_cache_file(
+            "0123456789abcdef-fedcba9876543210.parquet.a1B2c3.tmp"
+        ));
+        assert!(!is_cache_file("0123456789abcdef.tmp"));
+        assert!(!is_cache_file("notes.data"));
+        assert!(!is_cache_file("0123456789abcdef.csv"));
+    }
+
+    #[tokio::test]
+    async fn cache_scan_should_use_parquet() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path());
+        let url = "https://a.com/b.csv";
+        let table = TableSource::new(url);
+        cache
+            .put(&meta(url, now()), b"a,b\n1,x\n2,y\n")
+            .await
+            .unwrap();
+
+        let df = cache.scan(&table).await.unwrap().collect().unwrap();
+        assert_eq!(df.shape(), (2, 2));
+        assert!(cache.parquet_path(&table).exists());
+
+        // 原始数据没有变化时直接读取 parquet，并且可以下推过滤条件
+        std::fs::write(cache.data_path(url), b"broken").unwrap();
+        let df = cache
+            .scan(&table)
+            .await
+            .unwrap()
+            .filter(col("a").gt(lit(1)))
+            .select([col("b")])
+            .collect()
+            .unwrap();
+        assert_eq!(df.shape(), (1, 1));
+        assert_eq!(df["b"].get(0).unwrap(), AnyValue::Utf8("y"));
+
+        // 写入新的原始数据之后 parquet 失效
+        cache.put(&meta(url, now()), b"a\n1\n").await.unwrap();
+        assert!(!cache.parquet_path(&table).exists());
+        let df = cache.scan(&table).await.unwrap().collect().unwrap();
+        assert_eq!(df.shape(), (1, 1));
+
+        // 不同的加载选项分别缓存
+        let table = table.with_options(LoadOptions {
+            has_header: Some(false),
+            ..Default::default()
+        });
+        let df = cache.scan(&table).await.unwrap().collect().unwrap();
+        assert_eq!(df.shape(), (2, 1));
+    }
+
+    #[test]
+    fn concurrent_put_parquet_should_not_conflict() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("0123456789abcdef-fedcba9876543210.parquet");
+        let writers: Vec<_> = (0..4)
+            .map(|i| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let mut df = df! { "a" => vec![i; 10000] }.unwrap();
+                    put_parquet(&path, &mut df).unwrap();
+                })
+            })
+            .collect();
+        writers.into_iter().for_each(|w| w.join().unwrap());
+
+        // 每次写入用单独的临时文件，最后留下的是某一次完整的写入
+        let df = scan_parquet(&path).unwrap().collect().unwrap();
+        assert_eq!(df.height(), 10000);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn cache_freshness_should_respect_ttl() {
+        let cache = Cache::new("/tmp").with_ttl(Duration::from_secs(60));
+        assert!(cache.is_fresh(&meta("u", now() - 10)));
+        assert!(!cache.is_fresh(&meta("u", now() - 120)));
+        assert!(!Cache::new("/tmp")
+            .with_ttl(Duration::ZERO)
+            .is_fresh(&meta("u", now())));
+    }
+}
@@ -0,0 +1,166 @@
+use std::{collections::HashMap, path::Path, str::FromStr};
+
+use serde::Deserialize;
+
+use crate::{
+    decompress::decompress,
+    error::{invalid, QueryError, Result},
+    fetcher::Content,
+    loader::{Format, LoadOptions, Loader},
+};
+
+/// 一个数据源：url 以及加载数据时使用的格式和选项
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct TableSource {
+    pub url: String,
+    /// 数据格式，没有设置时根据 url 和 Content-Type 推断
+    #[serde(default)]
+    pub format: Option<Format>,
+    #[serde(default)]
+    pub options: LoadOptions,
+}
+
+/// 表名到数据源的注册表，FROM 中的表名会先在这里查找
+///
+/// 可以在代码中构建，也可以从 toml 文件中加载：
+///
+/// ```toml
+/// [tables.covid]
+/// url = "https://example.com/covid.csv"
+/// format = "csv"
+/// options = { delimiter = ",", has_header = true }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Catalog {
+    #[serde(default)]
+    tables: HashMap<String, TableSource>,
+}
+
+impl TableSource {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_format(mut self, format: Format) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    pub fn with_options(mut self, options: LoadOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// 根据获取到的内容选择合适的 loader，设置了 format 时不再推断，压缩过的数据会先解压
+    pub(crate) fn loader(&self, content: Content) -> Result<Loader> {
+        let content = decompress(&self.url, content)?;
+        let format = match self.format {
+            Some(format) => format,
+            None => Format::detect(&self.url, content.content_type.as_deref())?,
+        };
+        Ok(Loader::new(format, content.data).with_options(self.options.clone()))
+    }
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 从 toml 文件中加载
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    /// 注册一张表，同名的表会被覆盖
+    pub fn register(&mut self, name: impl Into<String>, source: TableSource) -> &mut Self {
+        self.tables.insert(name.into(), source);
+        self
+    }
+
+    pub fn with_table(mut self, name: impl Into<String>, source: TableSource) -> Self {
+        self.register(name, source);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TableSource> {
+        self.tables.get(name)
+    }
+
+    /// 所有注册的表名，按字母顺序排列
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.tables.keys().map(|v| v.as_str()).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// 把 FROM 中的表名解析成数据源：注册过的表名优先，其次是带 scheme 的 url
+    pub fn resolve(&self, name: &str) -> Result<TableSource> {
+        match self.get(name) {
+            Some(source) => Ok(source.clone()),
+            None if name.contains("://") => Ok(TableSource::new(name)),
+            None => Err(invalid!("Table {} is not found in catalog", name)),
+        }
+    }
+}
+
+impl FromStr for Catalog {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(toml::from_str(s)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalog_from_toml_should_work() {
+        let catalog: Catalog = r#"
+            [tables.covid]
+            url = "https://a.com/covid"
+            format = "csv"
+            options = { delimiter = ";", has_header = false }
+
+            [tables.countries]
+            url = "file:///tmp/countries.json"
+        "#
+        .parse()
+        .unwrap();
+
+        assert_eq!(catalog.names(), ["countries", "covid"]);
+        let covid = catalog.resolve("covid").unwrap();
+        assert_eq!(covid.format, Some(Format::Csv));
+        assert_eq!(covid.options.delimiter, Some(';'));
+        assert_eq!(covid.options.has_header, Some(false));
+        assert_eq!(
+            catalog.resolve("countries").unwrap(),
+            TableSource::new("file:///tmp/countries.json")
+        );
+
+        assert!(r#"[tables.t]
+            url = "a"
+            format = "xls""#
+            .parse::<Catalog>()
+            .is_err());
+    }
+
+    #[test]
+    fn catalog_resolve_should_work() {
+        let catalog = Catalog::new().with_table(
+            "covid",
+            TableSource::new("https://a.com/data").with_format(Format::Json),
+        );
+        assert_eq!(catalog.resolve("covid").unwrap().url, "https://a.com/data");
+        assert_eq!(
+            catalog.resolve("https://b.com/x.csv").unwrap(),
+            TableSource::new("https://b.com/x.csv")
+        );
+        assert!(catalog.resolve("unknown").is_err());
+    }
+}
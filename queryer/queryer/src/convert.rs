@@ -0,0 +1,1775 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use polars::prelude::*;
+use sqlparser::ast::{
+    BinaryOperator as SqlBinaryOperator, Distinct, Expr as SqlExpr, GroupByExpr, JoinConstraint,
+    JoinOperator, Offset as SqlOffset, OrderByExpr, Query, Select, SelectItem, SetExpr, Statement,
+    TableFactor, TableWithJoins, UnaryOperator as SqlUnaryOperator, Value as SqlValue,
+};
+
+use crate::{
+    error::{invalid, node_name, unsupported, QueryError, Result},
+    function::{self, Function},
+};
+
+/// 解析出来的 SQL
+pub struct Sql<'a> {
+    pub(crate) selection: Vec<Expr>,
+    pub(crate) condition: Option<Expr>,
+    pub(crate) source: Table<'a>,
+    pub(crate) joins: Vec<Join<'a>>,
+    pub(crate) order_by: Vec<(Expr, bool)>,
+    pub(crate) offset: Option<i64>,
+    pub(crate) limit: Option<usize>,
+    pub(crate) group_by: Vec<Expr>,
+    // 需要在 group by 中计算的聚合表达式，selection 中通过别名引用它们
+    pub(crate) aggregation: Vec<Expr>,
+    pub(crate) having: Option<Expr>,
+    // 窗口函数，在聚合之后、排序之前计算，selection 中通过别名引用它们
+    pub(crate) windows: Vec<Expr>,
+    // WITH 子句中定义的临时表，按定义的顺序排列，后面的可以引用前面的
+    pub(crate) ctes: Vec<(&'a str, Sql<'a>)>,
+    // WHERE 中的 `expr [NOT] IN (SELECT ...)`
+    pub(crate) semi_joins: Vec<SemiJoin<'a>>,
+    // SELECT DISTINCT，在投影之后去重
+    pub(crate) distinct: bool,
+}
+
+/// FROM / JOIN 子句中的一张表
+pub struct Table<'a> {
+    pub(crate) source: &'a str,
+    pub(crate) alias: Option<&'a str>,
+    // FROM (SELECT ...) t 这样的子查询，此时 source 和 alias 都是子查询的别名
+    pub(crate) subquery: Option<Box<Sql<'a>>>,
+}
+
+/// WHERE 中用 AND 连接的 `expr [NOT] IN (SELECT ...)`，执行时转换成 semi join / anti join
+pub struct SemiJoin<'a> {
+    pub(crate) expr: Expr,
+    pub(crate) subquery: Sql<'a>,
+    pub(crate) anti: bool,
+}
+
+/// JOIN 子句
+pub struct Join<'a> {
+    pub(crate) table: Table<'a>,
+    pub(crate) kind: JoinKind,
+    // ON 子句中的等值条件，左右两边各是哪张表要在拿到 schema 之后才能确定
+    pub(crate) on: Vec<(Expr, Expr)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+    Inner,
+    Left,
+    Right,
+    Full,
+    Cross,
+}
+
+// 因为 Rust trait 的孤儿规则，我们如果要想对已有的类型实现已有的 trait，
+// 需要简单包装一下
+pub struct Expression(pub(crate) Box<SqlExpr>);
+pub struct Operation(pub(crate) SqlBinaryOperator);
+pub struct Projection<'a>(pub(crate) &'a SelectItem);
+pub struct Source<'a>(pub(crate) &'a [TableWithJoins]);
+pub struct Relation<'a>(pub(crate) &'a TableFactor);
+pub struct JoinCondition<'a>(pub(crate) &'a JoinOperator);
+pub struct Order<'a>(pub(crate) &'a OrderByExpr);
+pub struct Offset<'a>(pub(crate) &'a SqlOffset);
+pub struct Limit<'a>(pub(crate) &'a SqlExpr);
+pub struct Value(pub(crate) SqlValue);
+
+impl<'a> Sql<'a> {
+    /// 所有需要读取的数据源（FROM 以及 JOIN 子句中的 url），按出现的顺序排列
+    pub fn sources(&self) -> Vec<&'a str> {
+        self.tables().map(|t| t.source).collect()
+    }
+
+    /// 需要从外部读取的所有数据源，包括 WITH 子句和子查询中用到的，但不包括 WITH 中定义的临时表
+    pub fn external_sources(&self) -> Vec<&'a str> {
+        let defined: HashSet<&str> = self.ctes.iter().map(|(name, _)| *name).collect();
+        let nested = self
+            .ctes
+            .iter()
+            .map(|(_, cte)| cte)
+            .chain(self.tables().filter_map(|t| t.subquery.as_deref()))
+            .chain(self.semi_joins.iter().map(|s| &s.subquery))
+            .flat_map(|sql| sql.external_sources());
+        let direct = self
+            .tables()
+            .filter(|t| t.subquery.is_none())
+            .map(|t| t.source);
+
+        let mut sources = Vec::new();
+        for source in nested.chain(direct) {
+            if !defined.contains(source) && !sources.contains(&source) {
+                sources.push(source);
+            }
+        }
+        sources
+    }
+
+    /// 根据表名到数据的映射生成执行计划，WITH 中定义的临时表会依次加入映射中
+    pub fn build(mut self, tables: &HashMap<String, LazyFrame>) -> Result<LazyFrame> {
+        let mut tables = tables.clone();
+        for (name, cte) in std::mem::take(&mut self.ctes) {
+            let df = cte.build(&tables)?;
+            tables.insert(name.to_owned(), df);
+        }
+
+        // 子查询递归地生成执行计划，其它的表从映射中获取
+        let mut frames = Vec::new();
+        let tables_in_from =
+            std::iter::once(&mut self.source).chain(self.joins.iter_mut().map(|j| &mut j.table));
+        for table in tables_in_from {
+            let df = match table.subquery.take() {
+                Some(subquery) => subquery.build(&tables)?,
+                None => match tables.get(table.source) {
+                    Some(df) => df.clone(),
+                    None => return Err(invalid!("No data for source {}", table.source)),
+                },
+            };
+            frames.push(df);
+        }
+
+        let semi_joins = std::mem::take(&mut self.semi_joins)
+            .into_iter()
+            .map(|s| Ok((s.expr, s.subquery.build(&tables)?, s.anti)))
+            .collect::<Result<Vec<_>>>()?;
+        self.execute(frames, semi_joins)
+    }
+
+    /// 把 Sql 中的各个子句依次作用在 LazyFrame 上，生成最终的执行计划
+    ///
+    /// frames 是每个数据源读出来的数据，顺序和 `sources()` 一致；有子查询时需要使用 `build()`
+    pub fn apply(self, frames: Vec<LazyFrame>) -> Result<LazyFrame> {
+        if !self.semi_joins.is_empty() || self.tables().any(|t| t.subquery.is_some()) {
+            return Err(invalid!("Subqueries should be planned with build()"));
+        }
+        self.execute(frames, Vec::new())
+    }
+
+    // FROM / JOIN 中的所有表
+    fn tables(&self) -> impl Iterator<Item = &Table<'a>> {
+        std::iter::once(&self.source).chain(self.joins.iter().map(|j| &j.table))
+    }
+
+    // semi_joins 中是 (左边的表达式, 子查询的执行计划, 是否是 NOT IN)
+    fn execute(
+        mut self,
+        frames: Vec<LazyFrame>,
+        mut semi_joins: Vec<(Expr, LazyFrame, bool)>,
+    ) -> Result<LazyFrame> {
+        let mut frames = frames.into_iter();
+        let mut df = match frames.next() {
+            Some(df) => df,
+            None => return Err(invalid!("No data for source {}", self.source.source)),
+        };
+
+        // 有 join 时，所有的列都加上表名作为前缀，避免不同表中同名的列冲突
+        if !self.joins.is_empty() {
+            df = self.source.qualify(df);
+            for join in std::mem::take(&mut self.joins) {
+                let right = match frames.next() {
+                    Some(right) => join.table.qualify(right),
+                    None => return Err(invalid!("No data for source {}", join.table.source)),
+                };
+                df = join.apply(df, right)?;
+            }
+            let schema = df.schema()?;
+            self.resolve_columns(|expr, skip| resolve_columns(expr, &schema, skip))?;
+            for (expr, _, _) in semi_joins.iter_mut() {
+                *expr = resolve_columns(expr.clone(), &schema, &HashSet::new())?;
+            }
+        } else if self.source.alias.is_some() {
+            // 只有一张表时不加前缀，"别名.列名" 直接去掉别名
+            let (schema, table) = (df.schema()?, self.source.name());
+            self.resolve_columns(|expr, _| Ok(strip_qualifier(expr, table, &schema)))?;
+            for (expr, _, _) in semi_joins.iter_mut() {
+                *expr = strip_qualifier(expr.clone(), table, &schema);
+            }
+        }
+
+        self.plan(df, semi_joins)
+    }
+
+    /// 用 resolve 替换所有子句中的列，比如把没有带表名的列替换成带表名的列
+    fn resolve_columns<F>(&mut self, resolve: F) -> Result<()>
+    where
+        F: Fn(Expr, &HashSet<String>) -> Result<Expr>,
+    {
+        // 聚合结果和 group by key 的列名不在 schema 中，不需要处理
+        let skip: HashSet<String> = self
+            .aggregation
+            .iter()
+            .chain(self.group_by.iter())
+            .filter_map(|e| match e {
+                Expr::Alias(_, name) => Some(name.to_string()),
+                _ => None,
+            })
+            .collect();
+
+        for expr in self.selection.iter_mut() {
+            let resolved = resolve(expr.clone(), &skip)?;
+            // 对于 SELECT 中直接引用的列，保留原来的名字
+            *expr = match (&*expr, &resolved) {
+                (Expr::Column(name), Expr::Column(new)) if name != new => resolved.alias(name),
+                _ => resolved,
+            };
+        }
+        for expr in self
+            .group_by
+            .iter_mut()
+            .chain(self.aggregation.iter_mut())
+            .chain(self.windows.iter_mut())
+        {
+            *expr = resolve(expr.clone(), &skip)?;
+        }
+        for (expr, _) in self.order_by.iter_mut() {
+            *expr = resolve(expr.clone(), &skip)?;
+        }
+        if let Some(expr) = self.condition.take() {
+            self.condition = Some(resolve(expr, &skip)?);
+        }
+        if let Some(expr) = self.having.take() {
+            self.having = Some(resolve(expr, &skip)?);
+        }
+        Ok(())
+    }
+
+    fn plan(self, df: LazyFrame, semi_joins: Vec<(Expr, LazyFrame, bool)>) -> Result<LazyFrame> {
+        let mut filtered = match self.condition {
+            Some(expr) => df.filter(expr),
+            None => df,
+        };
+        for (expr, subquery, anti) in semi_joins {
+            filtered = semi_join(filtered, expr, subquery, anti)?;
+        }
+
+        // 有聚合时，先分组计算聚合结果，再用 having 过滤
+        if !self.group_by.is_empty() || !self.aggregation.is_empty() {
+            filtered = match self.group_by.is_empty() {
+                true => filtered.select(self.aggregation),
+                false => filtered
+                    .group_by_stable(self.group_by)
+                    .agg(self.aggregation),
+            };
+            if let Some(expr) = self.having {
+                filtered = filtered.filter(expr);
+            }
+        }
+
+        if !self.windows.is_empty() {
+            filtered = filtered.with_columns(self.windows);
+        }
+
+        if !self.order_by.is_empty() {
+            let (exprs, desc): (Vec<_>, Vec<_>) = self.order_by.into_iter().unzip();
+            filtered = filtered.sort_by_exprs(exprs, desc, false, false);
+        }
+
+        // DISTINCT 要在 LIMIT 之前去重，所以先投影再截取；unique_stable 保留排序后的顺序
+        filtered = filtered.select(self.selection);
+        if self.distinct {
+            filtered = filtered.unique_stable(None, UniqueKeepStrategy::First);
+        }
+
+        if self.offset.is_some() || self.limit.is_some() {
+            // 超过 IdxSize 的 limit 等同于不限制，直接转换会溢出
+            let limit = self.limit.map_or(IdxSize::MAX, |v| {
+                IdxSize::try_from(v).unwrap_or(IdxSize::MAX)
+            });
+            filtered = filtered.slice(self.offset.unwrap_or(0), limit);
+        }
+
+        Ok(filtered)
+    }
+}
+
+/// 把 SqlParser 解析出来的 Statement 转换成我们需要的结构
+impl<'a> TryFrom<&'a Statement> for Sql<'a> {
+    type Error = QueryError;
+
+    fn try_from(sql: &'a Statement) -> Result<Self, Self::Error> {
+        match sql {
+            // 目前我们只关心 query (select ... from ... where ...)
+            Statement::Query(q) => q.as_ref().try_into(),
+            v => Err(unsupported!(
+                node_name("Statement", v),
+                "We only support Query at the moment"
+            )),
+        }
+    }
+}
+
+/// 把 SqlParser 解析出来的 Query 转换成我们需要的结构
+impl<'a> TryFrom<&'a Query> for Sql<'a> {
+    type Error = QueryError;
+
+    fn try_from(q: &'a Query) -> Result<Self, Self::Error> {
+        let mut ctes = Vec::new();
+        if let Some(with) = &q.with {
+            if with.recursive {
+                return Err(unsupported!("With", "WITH RECURSIVE is not supported"));
+            }
+            for cte in with.cte_tables.iter() {
+                if !cte.alias.columns.is_empty() {
+                    return Err(unsupported!(
+                        "Cte",
+                        "Column list of CTE {} is not supported",
+                        cte.alias
+                    ));
+                }
+                ctes.push((
+                    cte.alias.name.value.as_str(),
+                    cte.query.as_ref().try_into()?,
+                ));
+            }
+        }
+
+        let Select {
+            distinct,
+            top,
+            projection,
+            into,
+            from: table_with_joins,
+            lateral_views,
+            selection: where_clause,
+            group_by: group_by_clause,
+            cluster_by,
+            distribute_by,
+            sort_by,
+            having: having_clause,
+            named_window,
+            qualify,
+        } = match q.body.as_ref() {
+            SetExpr::Select(statement) => statement.as_ref(),
+            v => {
+                return Err(unsupported!(
+                    node_name("SetExpr", v),
+                    "We only support Select Query at the moment"
+                ))
+            }
+        };
+
+        // 不支持的子句直接报错，而不是悄悄忽略掉
+        let distinct = match distinct {
+            None => false,
+            Some(Distinct::Distinct) => true,
+            Some(v @ Distinct::On(_)) => {
+                return Err(unsupported!("Distinct::On", "{} is not supported", v))
+            }
+        };
+        let clauses = [
+            (top.is_some(), "TOP"),
+            (into.is_some(), "SELECT INTO"),
+            (!lateral_views.is_empty(), "LATERAL VIEW"),
+            (!cluster_by.is_empty(), "CLUSTER BY"),
+            (!distribute_by.is_empty(), "DISTRIBUTE BY"),
+            (!sort_by.is_empty(), "SORT BY"),
+            (!named_window.is_empty(), "WINDOW"),
+            (qualify.is_some(), "QUALIFY"),
+        ];
+        if let Some((_, clause)) = clauses.iter().find(|(used, _)| *used) {
+            return Err(unsupported!("Select", "{} is not supported", clause));
+        }
+
+        let (source, joins) = Source(table_with_joins).try_into()?;
+
+        // WHERE 中用 AND 连接的 IN 子查询单独拿出来做 semi join，其它的条件再合并起来
+        let mut conditions: Vec<Expr> = Vec::new();
+        let mut semi_joins = Vec::new();
+        for expr in where_clause.iter().flat_map(conjuncts) {
+            match expr {
+                SqlExpr::InSubquery {
+                    expr,
+                    subquery,
+                    negated,
+                } => semi_joins.push(SemiJoin {
+                    expr: Expression(expr.clone()).try_into()?,
+                    subquery: subquery.as_ref().try_into()?,
+                    anti: *negated,
+                }),
+                e => conditions.push(Expression(Box::new(e.to_owned())).try_into()?),
+            }
+        }
+        let condition = conditions.into_iter().reduce(|a, b| a.and(b));
+
+        // 窗口函数单独计算，SELECT 中只引用计算的结果
+        let mut selection = Vec::with_capacity(8);
+        let mut windows = Vec::new();
+        for p in projection {
+            let expr: Expr = Projection(p).try_into()?;
+            selection.push(resolve_windows(expr, &mut windows));
+        }
+
+        let mut group_by = Vec::new();
+        match group_by_clause {
+            GroupByExpr::Expressions(exprs) => {
+                for expr in exprs {
+                    let expr = Expression(Box::new(expr.to_owned())).try_into()?;
+                    group_by.push(resolve_alias(expr, &selection));
+                }
+            }
+            GroupByExpr::All => {
+                return Err(unsupported!(
+                    "GroupByExpr::All",
+                    "GROUP BY ALL is not supported"
+                ))
+            }
+        }
+
+        let mut having = match having_clause {
+            Some(expr) => Some(Expression(Box::new(expr.to_owned())).try_into()?),
+            None => None,
+        };
+
+        // 把带聚合函数的列放到 group by 中计算，SELECT 中只引用计算的结果
+        let mut aggregation = Vec::new();
+        let aggregate =
+            !group_by.is_empty() || having.is_some() || selection.iter().any(has_aggregation);
+        if aggregate {
+            // group by 的 key 是表达式时要取名字：SELECT 中有相同的表达式时用它的别名，否则用生成的名字。
+            // 分组之后只能引用 key 的列，不然会在已经计算过的 key 上再计算一次
+            for (i, key) in group_by.iter_mut().enumerate() {
+                if let Expr::Column(_) = key {
+                    continue;
+                }
+                let name = selection
+                    .iter()
+                    .find_map(|e| match e {
+                        Expr::Alias(inner, name) if inner.as_ref() == key => Some(name.clone()),
+                        _ => None,
+                    })
+                    .unwrap_or_else(|| Arc::from(format!("__group_{}", i)));
+                *key = key.clone().alias(&name);
+            }
+
+            for (expr, p) in selection.iter_mut().zip(projection) {
+                if has_aggregation(expr) {
+                    let name = match p {
+                        SelectItem::ExprWithAlias { alias, .. } => alias.value.clone(),
+                        item => item.to_string(),
+                    };
+                    let inner = match &*expr {
+                        Expr::Alias(inner, _) => inner.as_ref().clone(),
+                        e => e.clone(),
+                    };
+                    aggregation.push(inner.alias(&name));
+                    *expr = col(&name);
+                }
+            }
+            for expr in selection.iter_mut() {
+                *expr = resolve_group_keys(expr.clone(), &group_by);
+            }
+            having = having.map(|expr| {
+                resolve_group_keys(resolve_aggregation(expr, &mut aggregation), &group_by)
+            });
+        }
+
+        // ORDER BY 中可以使用 SELECT 里定义的别名，这里替换成原始的表达式
+        let mut order_by = Vec::new();
+        for o in q.order_by.iter() {
+            let (mut expr, desc): (Expr, bool) = Order(o).try_into()?;
+            // ORDER BY 1 表示按 SELECT 中的第一列排序，SELECT 中的表达式已经处理过窗口和聚合
+            if let SqlExpr::Value(SqlValue::Number(n, _)) = &o.expr {
+                let expr = resolve_position(n, &selection)?;
+                order_by.push((resolve_group_keys(expr, &group_by), desc));
+                continue;
+            }
+            if let Expr::Literal(_) = expr {
+                return Err(unsupported!(
+                    "OrderByExpr",
+                    "ORDER BY constant {} is not supported",
+                    o.expr
+                ));
+            }
+            expr = resolve_windows(expr, &mut windows);
+            if aggregate {
+                expr = resolve_aggregation(expr, &mut aggregation);
+            }
+            expr = resolve_alias(expr, &selection);
+            order_by.push((resolve_group_keys(expr, &group_by), desc));
+        }
+
+        // 窗口函数在聚合之后计算，其中的聚合函数同样要引用 group by 计算的结果
+        if aggregate {
+            windows = windows
+                .into_iter()
+                .map(|expr| resolve_window_aggregation(expr, &mut aggregation))
+                .map(|expr| resolve_group_keys(expr, &group_by))
+                .collect();
+        }
+
+        let offset = match q.offset.as_ref() {
+            Some(v) => Some(Offset(v).try_into()?),
+            None => None,
+        };
+        let limit = match q.limit.as_ref() {
+            Some(v) => Some(Limit(v).try_into()?),
+            None => None,
+        };
+
+        Ok(Sql {
+            selection,
+            condition,
+            source,
+            joins,
+            order_by,
+            offset,
+            limit,
+            group_by,
+            aggregation,
+            having,
+            windows,
+            ctes,
+            semi_joins,
+            distinct,
+        })
+    }
+}
+
+/// 每个子句一行，展示 SQL 被转换成了哪些 DataFrame 表达式，子查询缩进显示
+impl<'a> fmt::Display for Sql<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, cte) in self.ctes.iter() {
+            writeln!(f, "WITH {}:", name)?;
+            write_nested(f, cte)?;
+        }
+        write_table(f, "FROM", &self.source)?;
+        for join in self.joins.iter() {
+            let kind = format!("{:?} JOIN", join.kind).to_uppercase();
+            write_table(f, &kind, &join.table)?;
+            for (left, right) in join.on.iter() {
+                writeln!(f, "  ON {:?} = {:?}", left, right)?;
+            }
+        }
+        if let Some(expr) = &self.condition {
+            writeln!(f, "WHERE {:?}", expr)?;
+        }
+        for semi in self.semi_joins.iter() {
+            let op = if semi.anti { "NOT IN" } else { "IN" };
+            writeln!(f, "WHERE {:?} {}:", semi.expr, op)?;
+            write_nested(f, &semi.subquery)?;
+        }
+        if !self.group_by.is_empty() {
+            writeln!(f, "GROUP BY {}", list(&self.group_by))?;
+        }
+        if !self.aggregation.is_empty() {
+            writeln!(f, "AGGREGATE {}", list(&self.aggregation))?;
+        }
+        if let Some(expr) = &self.having {
+            writeln!(f, "HAVING {:?}", expr)?;
+        }
+        if !self.windows.is_empty() {
+            writeln!(f, "WINDOW {}", list(&self.windows))?;
+        }
+        if !self.order_by.is_empty() {
+            let order_by: Vec<_> = self
+                .order_by
+                .iter()
+                .map(|(expr, desc)| format!("{:?} {}", expr, if *desc { "DESC" } else { "ASC" }))
+                .collect();
+            writeln!(f, "ORDER BY {}", order_by.join(", "))?;
+        }
+        if let Some(offset) = self.offset {
+            writeln!(f, "OFFSET {}", offset)?;
+        }
+        if let Some(limit) = self.limit {
+            writeln!(f, "LIMIT {}", limit)?;
+        }
+        let select = if self.distinct {
+            "SELECT DISTINCT"
+        } else {
+            "SELECT"
+        };
+        writeln!(f, "{} {}", select, list(&self.selection))
+    }
+}
+
+fn write_table(f: &mut fmt::Formatter<'_>, clause: &str, table: &Table) -> fmt::Result {
+    match (&table.subquery, table.alias) {
+        (Some(subquery), _) => {
+            writeln!(f, "{} {}:", clause, table.source)?;
+            write_nested(f, subquery)
+        }
+        (None, Some(alias)) => writeln!(f, "{} {} AS {}", clause, table.source, alias),
+        (None, None) => writeln!(f, "{} {}", clause, table.source),
+    }
+}
+
+fn write_nested(f: &mut fmt::Formatter<'_>, sql: &Sql) -> fmt::Result {
+    for line in sql.to_string().lines() {
+        writeln!(f, "  {}", line)?;
+    }
+    Ok(())
+}
+
+fn list(exprs: &[Expr]) -> String {
+    let exprs: Vec<_> = exprs.iter().map(|e| format!("{:?}", e)).collect();
+    exprs.join(", ")
+}
+
+// 把 AND 连接的条件拆开
+fn conjuncts(expr: &SqlExpr) -> Vec<&SqlExpr> {
+    match expr {
+        SqlExpr::BinaryOp {
+            left,
+            op: SqlBinaryOperator::And,
+            right,
+        } => {
+            let mut exprs = conjuncts(left);
+            exprs.extend(conjuncts(right));
+            exprs
+        }
+        SqlExpr::Nested(expr) => conjuncts(expr),
+        expr => vec![expr],
+    }
+}
+
+// 用 semi join 实现 `expr IN (SELECT ...)`，anti join 实现 NOT IN
+//
+// 按照 SQL 中 NULL 的语义：null 和任何值比较的结果都是 unknown，
+// IN 时两边的 null 都不能匹配；NOT IN 时子查询中只要有 null，结果就是空的，
+// 左边为 null 的行也只有在子查询为空时才保留
+fn semi_join(df: LazyFrame, expr: Expr, subquery: LazyFrame, anti: bool) -> Result<LazyFrame> {
+    let schema = subquery.schema()?;
+    let key = match schema.iter_names().collect::<Vec<_>>().as_slice() {
+        [name] => col(name),
+        _ => return Err(invalid!("Subquery in IN must return exactly one column")),
+    };
+
+    // 子查询的结果转换成和左边一样的类型，避免 join 时类型不匹配
+    let name = "__semi_key";
+    let df = df.with_column(expr.alias(name));
+    let dtype = df.schema()?.try_get(name)?.clone();
+    let right = subquery.select([key.cast(dtype).alias(name)]);
+    let keys = right.clone().filter(col(name).is_not_null());
+    if !anti {
+        let df = df.filter(col(name).is_not_null());
+        return Ok(df
+            .join(keys, [col(name)], [col(name)], JoinType::Semi.into())
+            .drop_columns([name]));
+    }
+
+    let (nulls, rows) = ("__semi_nulls", "__semi_rows");
+    let stats = right.select([
+        col(name).null_count().alias(nulls),
+        col(name).count().alias(rows),
+    ]);
+    let keep = col(nulls)
+        .eq(lit(0))
+        .and(col(name).is_not_null().or(col(rows).eq(lit(0))));
+    Ok(df
+        .join(keys, [col(name)], [col(name)], JoinType::Anti.into())
+        .cross_join(stats)
+        .filter(keep)
+        .drop_columns([name, nulls, rows]))
+}
+
+/// 如果表达式是 SELECT 中某个别名，返回别名对应的原始表达式
+fn resolve_alias(expr: Expr, selection: &[Expr]) -> Expr {
+    if let Expr::Column(name) = &expr {
+        for item in selection {
+            if let Expr::Alias(inner, alias) = item {
+                if alias == name {
+                    return inner.as_ref().clone();
+                }
+            }
+        }
+    }
+    expr
+}
+
+/// ORDER BY 中的数字 n 表示 SELECT 中的第 n 列（从 1 开始），返回该列未取别名的表达式
+fn resolve_position(n: &str, selection: &[Expr]) -> Result<Expr> {
+    let expr = n
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| selection.get(n.checked_sub(1)?))
+        .ok_or_else(|| invalid!("ORDER BY position {} is not in select list", n))?;
+    match expr {
+        Expr::Alias(inner, _) => Ok(inner.as_ref().clone()),
+        Expr::Wildcard => Err(unsupported!(
+            "OrderByExpr",
+            "ORDER BY position {} refers to *",
+            n
+        )),
+        e => Ok(e.clone()),
+    }
+}
+
+/// 把表达式中在 schema 里找不到的列，替换成 schema 中唯一以 ".列名" 结尾的列
+fn resolve_columns(mut expr: Expr, schema: &Schema, skip: &HashSet<String>) -> Result<Expr> {
+    let mut err = None;
+    expr.mutate().apply(|e| {
+        if let Expr::Column(name) = e {
+            if schema.contains(name) || skip.contains(name.as_ref()) {
+                return true;
+            }
+            let suffix = format!(".{}", name);
+            let found: Vec<_> = schema
+                .iter_names()
+                .filter(|n| n.ends_with(&suffix))
+                .collect();
+            match found.as_slice() {
+                [n] => *e = col(n),
+                [] => {}
+                _ => err = Some(invalid!("Column {} is ambiguous", name)),
+            }
+        }
+        true
+    });
+
+    match err {
+        Some(e) => Err(e),
+        None => Ok(expr),
+    }
+}
+
+/// 把 "表名.列名" 形式的列替换成 schema 中的列名
+fn strip_qualifier(mut expr: Expr, table: &str, schema: &Schema) -> Expr {
+    let prefix = format!("{}.", table);
+    expr.mutate().apply(|e| {
+        if let Expr::Column(name) = e {
+            match name.strip_prefix(&prefix) {
+                Some(column) if !schema.contains(name) && schema.contains(column) => {
+                    *e = col(column)
+                }
+                _ => {}
+            }
+        }
+        true
+    });
+    expr
+}
+
+/// 表达式中的列是否都在 schema 里
+fn columns_in(expr: &Expr, schema: &Schema) -> bool {
+    expr.into_iter().all(|e| match e {
+        Expr::Column(name) => schema.contains(name),
+        _ => true,
+    })
+}
+
+impl<'a> Table<'a> {
+    /// 表的名字：有别名时用别名，否则用 source
+    pub fn name(&self) -> &'a str {
+        self.alias.unwrap_or(self.source)
+    }
+
+    /// 所有的列都加上 "表名." 前缀
+    fn qualify(&self, df: LazyFrame) -> LazyFrame {
+        df.select([col("*").prefix(&format!("{}.", self.name()))])
+    }
+}
+
+impl<'a> Join<'a> {
+    /// 把 right join 到 left 上
+    fn apply(self, left: LazyFrame, right: LazyFrame) -> Result<LazyFrame> {
+        if self.kind == JoinKind::Cross {
+            return Ok(left.cross_join(right));
+        }
+
+        let (left_schema, right_schema) = (left.schema()?, right.schema()?);
+        let mut schema = left_schema.as_ref().clone();
+        schema.merge(right_schema.as_ref().clone());
+
+        // 确定等值条件的两边各属于哪张表；为了保留两边原始的 key 列，
+        // 把 key 计算到单独的列上再 join，join 完之后删掉
+        let (mut left_keys, mut right_keys, mut names) = (vec![], vec![], vec![]);
+        for (i, (a, b)) in self.on.into_iter().enumerate() {
+            let a = resolve_columns(a, &schema, &HashSet::new())?;
+            let b = resolve_columns(b, &schema, &HashSet::new())?;
+            let (l, r) = if columns_in(&a, &left_schema) && columns_in(&b, &right_schema) {
+                (a, b)
+            } else if columns_in(&b, &left_schema) && columns_in(&a, &right_schema) {
+                (b, a)
+            } else {
+                return Err(invalid!(
+                    "Join condition on table {} must compare columns of both tables",
+                    self.table.name()
+                ));
+            };
+            let name = format!("__join_key_{}", i);
+            left_keys.push(l.alias(&name));
+            right_keys.push(r.alias(&name));
+            names.push(name);
+        }
+        let keys: Vec<_> = names.iter().map(|n| col(n)).collect();
+
+        let (left, right) = (left.with_columns(left_keys), right.with_columns(right_keys));
+        let df = match self.kind {
+            JoinKind::Inner => left.join(right, &keys, &keys, JoinType::Inner.into()),
+            JoinKind::Left => left.join(right, &keys, &keys, JoinType::Left.into()),
+            JoinKind::Full => left.join(right, &keys, &keys, JoinType::Outer.into()),
+            // polars 没有 right join，交换两边做 left join
+            JoinKind::Right => right.join(left, &keys, &keys, JoinType::Left.into()),
+            JoinKind::Cross => unreachable!(),
+        };
+        Ok(df.drop_columns(names))
+    }
+}
+
+/// 表达式本身是否是聚合函数
+fn is_aggregation(expr: &Expr) -> bool {
+    matches!(expr, Expr::Agg(_) | Expr::Count)
+}
+
+/// 表达式中是否包含聚合函数
+fn has_aggregation(expr: &Expr) -> bool {
+    expr.into_iter().any(is_aggregation)
+}
+
+/// 把表达式中的聚合函数替换成 group by 计算结果的列，
+/// 如果 SELECT 里没有对应的聚合，就额外计算一个
+fn resolve_aggregation(mut expr: Expr, aggregation: &mut Vec<Expr>) -> Expr {
+    expr.mutate().apply(|e| {
+        if is_aggregation(e) {
+            let found = aggregation.iter().find_map(|agg| match agg {
+                Expr::Alias(inner, name) if inner.as_ref() == e => Some(name.clone()),
+                _ => None,
+            });
+            let name = match found {
+                Some(name) => name,
+                None => {
+                    let name: Arc<str> = Arc::from(format!("__agg_{}", aggregation.len()));
+                    aggregation.push(e.clone().alias(&name));
+                    name
+                }
+            };
+            *e = col(&name);
+        }
+        true
+    });
+    expr
+}
+
+/// 把表达式中和 group by key 相同的部分替换成 key 的列，比如 `GROUP BY YEAR(d)` 时 SELECT 中的 `YEAR(d)`
+///
+/// 只有取了名字的 key（表达式）需要替换，直接按列分组时分组结果中的列名不变
+fn resolve_group_keys(mut expr: Expr, keys: &[Expr]) -> Expr {
+    expr.mutate().apply(|e| {
+        let found = keys.iter().find_map(|key| match key {
+            Expr::Alias(inner, name) if inner.as_ref() == e => Some(name.clone()),
+            _ => None,
+        });
+        if let Some(name) = found {
+            *e = col(&name);
+        }
+        true
+    });
+    expr
+}
+
+/// 把窗口函数中的聚合函数替换成 group by 计算结果的列，比如 `RANK() OVER (ORDER BY SUM(x))`
+///
+/// 窗口函数本身是聚合函数时（`SUM(SUM(x)) OVER ()`）要保留，只替换它参数中的聚合
+fn resolve_window_aggregation(mut expr: Expr, aggregation: &mut Vec<Expr>) -> Expr {
+    expr.mutate().apply(|e| {
+        if let Expr::Window {
+            function,
+            partition_by,
+            ..
+        } = e
+        {
+            let f = std::mem::replace(function.as_mut(), Expr::Wildcard);
+            **function = match is_aggregation(&f) {
+                true => resolve_nested_aggregation(f, aggregation),
+                false => resolve_aggregation(f, aggregation),
+            };
+            for expr in partition_by.iter_mut() {
+                *expr = resolve_aggregation(expr.clone(), aggregation);
+            }
+            return false;
+        }
+        true
+    });
+    expr
+}
+
+// 和 resolve_aggregation 一样，但是不替换最外层的聚合函数
+fn resolve_nested_aggregation(mut expr: Expr, aggregation: &mut Vec<Expr>) -> Expr {
+    let mut root = true;
+    expr.mutate().apply(|e| {
+        if std::mem::take(&mut root) {
+            return true;
+        }
+        *e = resolve_aggregation(e.clone(), aggregation);
+        false
+    });
+    expr
+}
+
+/// 把表达式中的窗口函数替换成单独计算的列
+fn resolve_windows(mut expr: Expr, windows: &mut Vec<Expr>) -> Expr {
+    expr.mutate().apply(|e| {
+        if let Expr::Window { .. } = e {
+            let name = format!("__window_{}", windows.len());
+            let window = std::mem::replace(e, col(&name));
+            windows.push(window.alias(&name));
+        }
+        true
+    });
+    expr
+}
+
+/// 把 SqlParser 的 Expr 转换成 DataFrame 的 Expr
+impl TryFrom<Expression> for Expr {
+    type Error = QueryError;
+
+    fn try_from(expr: Expression) -> Result<Self, Self::Error> {
+        match *expr.0 {
+            SqlExpr::BinaryOp {
+                left,
+                op:
+                    op @ (SqlBinaryOperator::PGRegexMatch
+                    | SqlBinaryOperator::PGRegexIMatch
+                    | SqlBinaryOperator::PGRegexNotMatch
+                    | SqlBinaryOperator::PGRegexNotIMatch),
+                right,
+            } => {
+                let (negated, insensitive) = match op {
+                    SqlBinaryOperator::PGRegexMatch => (false, false),
+                    SqlBinaryOperator::PGRegexIMatch => (false, true),
+                    SqlBinaryOperator::PGRegexNotMatch => (true, false),
+                    _ => (true, true),
+                };
+                let pattern: Expr = Expression(right).try_into()?;
+                let pattern = match insensitive {
+                    true => concat_str([lit("(?i)"), pattern], ""),
+                    false => pattern,
+                };
+                let expr: Expr = Expression(left).try_into()?;
+                Ok(negate(expr.str().contains(pattern, true), negated))
+            }
+            SqlExpr::BinaryOp { left, op, right } => Ok(Expr::BinaryExpr {
+                left: Box::new(Expression(left).try_into()?),
+                op: Operation(op).try_into()?,
+                right: Box::new(Expression(right).try_into()?),
+            }),
+            SqlExpr::UnaryOp { op, expr } => {
+                let expr: Expr = Expression(expr).try_into()?;
+                match op {
+                    SqlUnaryOperator::Not => Ok(expr.not()),
+                    SqlUnaryOperator::Minus => Ok(lit(0) - expr),
+                    SqlUnaryOperator::Plus => Ok(expr),
+                    v => Err(unsupported!(
+                        node_name("UnaryOperator", &v),
+                        "Unary operator {} is not supported",
+                        v
+                    )),
+                }
+            }
+            SqlExpr::Nested(expr) => Expression(expr).try_into(),
+            SqlExpr::Identifier(id) => Ok(col(&id.value)),
+            SqlExpr::Value(v) => Value(v).try_into(),
+            SqlExpr::Function(f) => Function(f).try_into(),
+            SqlExpr::Cast { expr, data_type } | SqlExpr::TryCast { expr, data_type } => {
+                function::cast(Expression(expr).try_into()?, &data_type)
+            }
+            SqlExpr::Case {
+                operand,
+                conditions,
+                results,
+                else_result,
+            } => function::case_when(
+                optional(operand)?,
+                exprs(conditions)?,
+                exprs(results)?,
+                optional(else_result)?,
+            ),
+            SqlExpr::Substring {
+                expr,
+                substring_from,
+                substring_for,
+                ..
+            } => function::substring(
+                Expression(expr).try_into()?,
+                optional(substring_from)?,
+                optional(substring_for)?,
+            ),
+            SqlExpr::Trim {
+                expr,
+                trim_where,
+                trim_what,
+            } => function::trim(
+                Expression(expr).try_into()?,
+                trim_where.as_ref(),
+                optional(trim_what)?,
+            ),
+            SqlExpr::Extract { field, expr } => {
+                function::extract(&field, Expression(expr).try_into()?)
+            }
+            SqlExpr::InSubquery { .. } => Err(unsupported!(
+                "Expr::InSubquery",
+                "IN (SELECT ...) is only supported in WHERE conditions joined by AND"
+            )),
+            SqlExpr::IsNull(expr) => Ok(Expr::try_from(Expression(expr))?.is_null()),
+            SqlExpr::IsNotNull(expr) => Ok(Expr::try_from(Expression(expr))?.is_not_null()),
+            SqlExpr::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                let expr: Expr = Expression(expr).try_into()?;
+                let matched = exprs(list)?
+                    .into_iter()
+                    .map(|item| expr.clone().eq(item))
+                    .reduce(|acc, e| acc.or(e))
+                    .unwrap_or_else(|| lit(false));
+                Ok(negate(matched, negated))
+            }
+            SqlExpr::Between {
+                expr,
+                negated,
+                low,
+                high,
+            } => {
+                let expr: Expr = Expression(expr).try_into()?;
+                let low: Expr = Expression(low).try_into()?;
+                let high: Expr = Expression(high).try_into()?;
+                Ok(negate(
+                    expr.clone().gt_eq(low).and(expr.lt_eq(high)),
+                    negated,
+                ))
+            }
+            SqlExpr::Like {
+                negated,
+                expr,
+                pattern,
+                escape_char,
+            } => like(expr, *pattern, escape_char, false, negated),
+            SqlExpr::ILike {
+                negated,
+                expr,
+                pattern,
+                escape_char,
+            } => like(expr, *pattern, escape_char, true, negated),
+            v => Err(unsupported!(
+                node_name("Expr", &v),
+                "Expression {} is not supported",
+                v
+            )),
+        }
+    }
+}
+
+// NOT LIKE / NOT IN / NOT BETWEEN 等都是在原来的结果上取反
+fn negate(expr: Expr, negated: bool) -> Expr {
+    match negated {
+        true => expr.not(),
+        false => expr,
+    }
+}
+
+// LIKE 的 pattern 只支持字符串字面量，转换成等价的正则后用 str().contains 匹配
+fn like(
+    expr: Box<SqlExpr>,
+    pattern: SqlExpr,
+    escape_char: Option<char>,
+    insensitive: bool,
+    negated: bool,
+) -> Result<Expr> {
+    let pattern = match pattern {
+        SqlExpr::Value(SqlValue::SingleQuotedString(v))
+        | SqlExpr::Value(SqlValue::DoubleQuotedString(v)) => v,
+        v => {
+            return Err(unsupported!(
+                node_name("Expr", &v),
+                "LIKE pattern {} is not supported",
+                v
+            ))
+        }
+    };
+    let regex = like_to_regex(&pattern, escape_char, insensitive);
+    let expr: Expr = Expression(expr).try_into()?;
+    Ok(negate(expr.str().contains(lit(regex), true), negated))
+}
+
+// '%' 匹配任意多个字符，'_' 匹配单个字符，其它字符按字面匹配
+fn like_to_regex(pattern: &str, escape_char: Option<char>, insensitive: bool) -> String {
+    let mut regex = String::from(if insensitive { "(?is)^" } else { "(?s)^" });
+    let mut chars = pattern.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            c if Some(c) == escape_char => {
+                if let Some(next) = chars.next() {
+                    push_literal(&mut regex, next);
+                }
+            }
+            '%' => regex.push_str(".*"),
+            '_' => regex.push('.'),
+            c => push_literal(&mut regex, c),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+fn push_literal(regex: &mut String, ch: char) {
+    if "\\.+*?()|[]{}^$#&-~".contains(ch) {
+        regex.push('\\');
+    }
+    regex.push(ch);
+}
+
+// 转换可选的子表达式
+fn optional(expr: Option<Box<SqlExpr>>) -> Result<Option<Expr>> {
+    match expr {
+        Some(expr) => Ok(Some(Expression(expr).try_into()?)),
+        None => Ok(None),
+    }
+}
+
+// 转换一组子表达式
+fn exprs(exprs: Vec<SqlExpr>) -> Result<Vec<Expr>> {
+    exprs
+        .into_iter()
+        .map(|e| Expression(Box::new(e)).try_into())
+        .collect()
+}
+
+/// 把 SqlParser 的 BinaryOperator 转换成 DataFrame 的 Operator
+impl TryFrom<Operation> for Operator {
+    type Error = QueryError;
+
+    fn try_from(op: Operation) -> Result<Self, Self::Error> {
+        match op.0 {
+            SqlBinaryOperator::Plus => Ok(Self::Plus),
+            SqlBinaryOperator::Minus => Ok(Self::Minus),
+            SqlBinaryOperator::Multiply => Ok(Self::Multiply),
+            SqlBinaryOperator::Divide => Ok(Self::Divide),
+            SqlBinaryOperator::Modulo => Ok(Self::Modulus),
+            SqlBinaryOperator::Gt => Ok(Self::Gt),
+            SqlBinaryOperator::Lt => Ok(Self::Lt),
+            SqlBinaryOperator::GtEq => Ok(Self::GtEq),
+            SqlBinaryOperator::LtEq => Ok(Self::LtEq),
+            SqlBinaryOperator::Eq => Ok(Self::Eq),
+            SqlBinaryOperator::NotEq => Ok(Self::NotEq),
+            SqlBinaryOperator::And => Ok(Self::And),
+            SqlBinaryOperator::Or => Ok(Self::Or),
+            SqlBinaryOperator::Xor => Ok(Self::Xor),
+            v => Err(unsupported!(
+                node_name("BinaryOperator", &v),
+                "Operator {} is not supported",
+                v
+            )),
+        }
+    }
+}
+
+/// 把 SqlParser 的 SelectItem 转换成 DataFrame 的 Expr
+impl<'a> TryFrom<Projection<'a>> for Expr {
+    type Error = QueryError;
+
+    fn try_from(p: Projection<'a>) -> Result<Self, Self::Error> {
+        match p.0 {
+            // 没有别名的计算列用 SQL 原文命名，否则 polars 会沿用第一个列名，`UPPER(a)` 会和 `a` 重名
+            SelectItem::UnnamedExpr(expr) => {
+                let expr: Expr = Expression(Box::new(expr.to_owned())).try_into()?;
+                match expr {
+                    Expr::Column(_) | Expr::Wildcard => Ok(expr),
+                    expr => Ok(expr.alias(&p.0.to_string())),
+                }
+            }
+            SelectItem::ExprWithAlias { expr, alias } => {
+                let expr: Expr = Expression(Box::new(expr.to_owned())).try_into()?;
+                Ok(expr.alias(&alias.value))
+            }
+            SelectItem::Wildcard(_) => Ok(col("*")),
+            item => Err(unsupported!(
+                node_name("SelectItem", item),
+                "projection {} not supported",
+                item
+            )),
+        }
+    }
+}
+
+/// 把 SqlParser 的 FROM 子句转换成主表和 join 的表
+impl<'a> TryFrom<Source<'a>> for (Table<'a>, Vec<Join<'a>>) {
+    type Error = QueryError;
+
+    fn try_from(source: Source<'a>) -> Result<Self, Self::Error> {
+        if source.0.len() != 1 {
+            return Err(unsupported!(
+                "TableWithJoins",
+                "We only support single data source at the moment"
+            ));
+        }
+
+        let table = &source.0[0];
+        let mut joins = Vec::with_capacity(table.joins.len());
+        for join in table.joins.iter() {
+            let (kind, on) = JoinCondition(&join.join_operator).try_into()?;
+            joins.push(Join {
+                table: Relation(&join.relation).try_into()?,
+                kind,
+                on,
+            });
+        }
+
+        Ok((Relation(&table.relation).try_into()?, joins))
+    }
+}
+
+/// 把 SqlParser 的 TableFactor 转换成 Table
+impl<'a> TryFrom<Relation<'a>> for Table<'a> {
+    type Error = QueryError;
+
+    fn try_from(relation: Relation<'a>) -> Result<Self, Self::Error> {
+        match relation.0 {
+            TableFactor::Table { name, alias, .. } => Ok(Table {
+                source: &name.0.first().unwrap().value,
+                alias: alias.as_ref().map(|a| a.name.value.as_str()),
+                subquery: None,
+            }),
+            TableFactor::Derived {
+                lateral: false,
+                subquery,
+                alias,
+            } => {
+                if let Some(alias) = alias.as_ref().filter(|a| !a.columns.is_empty()) {
+                    return Err(unsupported!(
+                        "TableAlias",
+                        "Column list of subquery {} is not supported",
+                        alias
+                    ));
+                }
+                let alias = alias.as_ref().map(|a| a.name.value.as_str());
+                Ok(Table {
+                    source: alias.unwrap_or("subquery"),
+                    alias,
+                    subquery: Some(Box::new(subquery.as_ref().try_into()?)),
+                })
+            }
+            v => Err(unsupported!(
+                node_name("TableFactor", v),
+                "We only support table"
+            )),
+        }
+    }
+}
+
+/// 把 SqlParser 的 JoinOperator 转换成 join 的类型和等值条件
+impl<'a> TryFrom<JoinCondition<'a>> for (JoinKind, Vec<(Expr, Expr)>) {
+    type Error = QueryError;
+
+    fn try_from(op: JoinCondition<'a>) -> Result<Self, Self::Error> {
+        let (kind, constraint) = match op.0 {
+            JoinOperator::Inner(c) => (JoinKind::Inner, c),
+            JoinOperator::LeftOuter(c) => (JoinKind::Left, c),
+            JoinOperator::RightOuter(c) => (JoinKind::Right, c),
+            JoinOperator::FullOuter(c) => (JoinKind::Full, c),
+            JoinOperator::CrossJoin => return Ok((JoinKind::Cross, vec![])),
+            v => {
+                return Err(unsupported!(
+                    node_name("JoinOperator", v),
+                    "Join {:?} is not supported",
+                    v
+                ))
+            }
+        };
+
+        let mut on = Vec::new();
+        match constraint {
+            JoinConstraint::On(expr) => split_join_condition(expr, &mut on)?,
+            v => {
+                return Err(unsupported!(
+                    node_name("JoinConstraint", v),
+                    "Join constraint {:?} is not supported",
+                    v
+                ))
+            }
+        }
+        Ok((kind, on))
+    }
+}
+
+/// 把 `a.x = b.x AND a.y = b.y` 拆成多个等值条件
+fn split_join_condition(expr: &SqlExpr, on: &mut Vec<(Expr, Expr)>) -> Result<()> {
+    match expr {
+        SqlExpr::BinaryOp {
+            left,
+            op: SqlBinaryOperator::And,
+            right,
+        } => {
+            split_join_condition(left, on)?;
+            split_join_condition(right, on)
+        }
+        SqlExpr::BinaryOp {
+            left,
+            op: SqlBinaryOperator::Eq,
+            right,
+        } => {
+            on.push((
+                Expression(left.to_owned()).try_into()?,
+                Expression(right.to_owned()).try_into()?,
+            ));
+            Ok(())
+        }
+        SqlExpr::Nested(expr) => split_join_condition(expr, on),
+        v => Err(unsupported!(
+            node_name("Expr", v),
+            "Join condition {} is not supported",
+            v
+        )),
+    }
+}
+
+/// 把 SqlParser 的 OrderByExpr 转换成 (排序表达式, 是否降序)
+impl<'a> TryFrom<Order<'a>> for (Expr, bool) {
+    type Error = QueryError;
+
+    fn try_from(o: Order<'a>) -> Result<Self, Self::Error> {
+        let expr = Expression(Box::new(o.0.expr.to_owned())).try_into()?;
+        Ok((expr, !o.0.asc.unwrap_or(true)))
+    }
+}
+
+/// 把 SqlParser 的 offset expr 转换成 i64
+impl<'a> TryFrom<Offset<'a>> for i64 {
+    type Error = QueryError;
+
+    fn try_from(offset: Offset<'a>) -> Result<Self, Self::Error> {
+        match offset.0.value {
+            SqlExpr::Value(SqlValue::Number(ref v, _)) => {
+                v.parse().map_err(|_| invalid!("Invalid offset {}", v))
+            }
+            ref v => Err(unsupported!(
+                node_name("Expr", v),
+                "offset {} is not supported",
+                v
+            )),
+        }
+    }
+}
+
+/// 把 SqlParser 的 limit expr 转换成 usize
+impl<'a> TryFrom<Limit<'a>> for usize {
+    type Error = QueryError;
+
+    fn try_from(l: Limit<'a>) -> Result<Self, Self::Error> {
+        match l.0 {
+            SqlExpr::Value(SqlValue::Number(v, _)) => {
+                v.parse().map_err(|_| invalid!("Invalid limit {}", v))
+            }
+            v => Err(unsupported!(
+                node_name("Expr", &v),
+                "limit {} is not supported",
+                v
+            )),
+        }
+    }
+}
+
+/// 把 SqlParser 的 Value 转换成 DataFrame 支持的字面量
+impl TryFrom<Value> for Expr {
+    type Error = QueryError;
+
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match v.0 {
+            SqlValue::Number(v, _) => match v.parse::<i64>() {
+                Ok(n) => Ok(lit(n)),
+                Err(_) => match v.parse::<f64>() {
+                    Ok(v) => Ok(lit(v)),
+                    Err(_) => Err(invalid!("Invalid number {}", v)),
+                },
+            },
+            SqlValue::SingleQuotedString(s) | SqlValue::DoubleQuotedString(s) => Ok(lit(s)),
+            SqlValue::Boolean(b) => Ok(lit(b)),
+            SqlValue::Null => Ok(lit(NULL)),
+            v => Err(unsupported!(
+                node_name("Value", &v),
+                "Value {} is not supported",
+                v
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialect::{example_sql, TryDialect};
+    use sqlparser::{dialect::GenericDialect, parser::Parser};
+
+    #[test]
+    fn parse_example_sql_should_work() {
+        let sql = example_sql();
+        let statement = &Parser::parse_sql(&TryDialect, &sql).unwrap()[0];
+        let sql: Sql = statement.try_into().unwrap();
+        assert_eq!(sql.sources(), vec!["https://raw.githubusercontent.com/owid/covid-19-data/master/public/data/latest/owid-covid-latest.csv"]);
+        assert_eq!(sql.limit, Some(6));
+        assert_eq!(sql.offset, Some(5));
+        assert_eq!(sql.order_by, vec![(col("new_cases"), true)]);
+        assert_eq!(
+            sql.selection,
+            vec![
+                col("location").alias("name"),
+                col("total_cases"),
+                col("new_cases"),
+                col("total_deaths"),
+                col("new_deaths")
+            ]
+        );
+        assert_eq!(sql.condition, Some(col("new_deaths").gt_eq(lit(500i64))));
+    }
+
+    #[test]
+    fn apply_should_filter_sort_and_slice() {
+        let df = df! {
+            "location" => ["A", "B", "C", "D"],
+            "new_cases" => [1, 4, 3, 2],
+        }
+        .unwrap();
+
+        let sql = "SELECT location name, new_cases FROM t WHERE new_cases > 1 ORDER BY name DESC LIMIT 2 OFFSET 1";
+        let statement = &Parser::parse_sql(&TryDialect, sql).unwrap()[0];
+        let sql: Sql = statement.try_into().unwrap();
+        let result = sql.apply(vec![df.lazy()]).unwrap().collect().unwrap();
+
+        let expected = df! {
+            "name" => ["C", "B"],
+            "new_cases" => [3, 4],
+        }
+        .unwrap();
+        assert!(result.frame_equal(&expected));
+    }
+
+    #[test]
+    fn order_by_position_should_work() {
+        let result = run(
+            "SELECT location, new_cases n FROM t ORDER BY 2 DESC",
+            covid(),
+        );
+        assert_eq!(result.height(), 5);
+        assert_eq!(result["location"].get(0).unwrap(), AnyValue::Utf8("World"));
+
+        let sql = "SELECT continent, SUM(new_cases) FROM t GROUP BY continent ORDER BY 2 LIMIT 2";
+        let result = run(sql, covid());
+        assert_eq!(
+            result["continent"].get(0).unwrap(),
+            AnyValue::Utf8("Europe")
+        );
+        assert_eq!(result.height(), 2);
+
+        // 超过 IdxSize 的 limit 不会溢出
+        let result = run(
+            "SELECT location FROM t ORDER BY 1 LIMIT 4294967296",
+            covid(),
+        );
+        assert_eq!(result.height(), 5);
+
+        for sql in [
+            "SELECT location FROM t ORDER BY 2",
+            "SELECT location FROM t ORDER BY 0",
+            "SELECT * FROM t ORDER BY 1",
+            "SELECT location FROM t ORDER BY 'a'",
+        ] {
+            let statement = &Parser::parse_sql(&TryDialect, sql).unwrap()[0];
+            assert!(Sql::try_from(statement).is_err(), "{} should fail", sql);
+        }
+    }
+
+    #[test]
+    fn distinct_should_work() {
+        let result = run(
+            "SELECT DISTINCT continent FROM t ORDER BY location",
+            covid(),
+        );
+        let expected = df! { "continent" => [Some("Asia"), Some("Europe"), None] }.unwrap();
+        assert!(result.frame_equal_missing(&expected));
+
+        // 先去重再 LIMIT
+        let result = run("SELECT DISTINCT continent FROM t LIMIT 2", covid());
+        assert_eq!(result.height(), 2);
+
+        for sql in [
+            "SELECT DISTINCT ON (continent) location FROM t",
+            "SELECT TOP 2 location FROM t",
+            "SELECT location FROM t QUALIFY new_cases > 1",
+            "SELECT location FROM t WINDOW w AS (ORDER BY new_cases)",
+        ] {
+            let statement = &Parser::parse_sql(&GenericDialect, sql).unwrap()[0];
+            let result = Sql::try_from(statement);
+            assert!(
+                matches!(result, Err(QueryError::Unsupported { .. })),
+                "{}",
+                sql
+            );
+        }
+    }
+
+    fn run(sql: &str, df: DataFrame) -> DataFrame {
+        run_many(sql, vec![df])
+    }
+
+    fn run_many(sql: &str, frames: Vec<DataFrame>) -> DataFrame {
+        let statement = &Parser::parse_sql(&TryDialect, sql).unwrap()[0];
+        let sql: Sql = statement.try_into().unwrap();
+        let frames = frames.into_iter().map(|df| df.lazy()).collect();
+        sql.apply(frames).unwrap().collect().unwrap()
+    }
+
+    fn covid() -> DataFrame {
+        df! {
+            "continent" => [Some("Asia"), Some("Europe"), Some("Asia"), Some("Europe"), None],
+            "location" => ["China", "France", "Japan", "Italy", "World"],
+            "new_cases" => [Some(10), Some(20), Some(30), None, Some(100)],
+        }
+        .unwrap()
+    }
+
+    #[test]
+    fn group_by_should_work() {
+        let sql = "SELECT continent, COUNT(*) n, COUNT(new_cases), SUM(new_cases) total, \
+            AVG(new_cases) avg, MIN(new_cases), MAX(new_cases) \
+            FROM t GROUP BY continent ORDER BY total DESC";
+        let result = run(sql, covid());
+        let expected = df! {
+            "continent" => [None, Some("Asia"), Some("Europe")],
+            "n" => [1u32, 2, 2],
+            "COUNT(new_cases)" => [1u32, 2, 1],
+            "total" => [100, 40, 20],
+            "avg" => [100.0, 20.0, 20.0],
+            "MIN(new_cases)" => [100, 10, 20],
+            "MAX(new_cases)" => [100, 30, 20],
+        }
+        .unwrap();
+        assert!(result.frame_equal_missing(&expected));
+    }
+
+    #[test]
+    fn window_over_aggregation_should_work() {
+        let sql = "SELECT continent, SUM(new_cases) total, \
+            RANK() OVER (ORDER BY SUM(new_cases) DESC) r, SUM(SUM(new_cases)) OVER () all_cases \
+            FROM t GROUP BY continent ORDER BY r";
+        let result = run(sql, covid());
+        let expected = df! {
+            "continent" => [None, Some("Asia"), Some("Europe")],
+            "total" => [100, 40, 20],
+            "r" => [1u32, 2, 3],
+            "all_cases" => [160, 160, 160],
+        }
+        .unwrap();
+        assert!(result.frame_equal_missing(&expected));
+    }
+
+    #[test]
+    fn having_should_work() {
+        let sql = "SELECT continent c, COUNT(DISTINCT location) countries FROM t \
+            WHERE new_cases > 5 GROUP BY c HAVING SUM(new_cases) >= 40 ORDER BY c";
+        let result = run(sql, covid());
+        let expected = df! {
+            "c" => [None, Some("Asia")],
+            "countries" => [1u32, 2],
+        }
+        .unwrap();
+        assert!(result.frame_equal_missing(&expected));
+    }
+
+    #[test]
+    fn group_by_expression_should_work() {
+        let df = df! {
+            "d" => ["2021-01-05", "2021-07-01", "2022-03-02", "2023-11-30"],
+            "x" => [12, 18, 25, 31],
+        }
+        .unwrap();
+
+        // 分组之后 SELECT、HAVING、ORDER BY 中的表达式引用 key 的结果，而不是再计算一次
+        let sql = "SELECT YEAR(d) y, SUM(x) s FROM t GROUP BY YEAR(d) \
+            HAVING YEAR(d) < 2023 ORDER BY YEAR(d) DESC";
+        let result = run(sql, df.clone());
+        let expected = df! { "y" => [2022, 2021], "s" => [25, 30] }.unwrap();
+        assert!(result.frame_equal(&expected));
+
+        let sql = "SELECT x / 10 AS b, COUNT(*) n FROM t GROUP BY b ORDER BY b";
+        let result = run(sql, df.clone());
+        let expected = df! { "b" => [1, 2, 3], "n" => [2u32, 1, 1] }.unwrap();
+        assert!(result.frame_equal(&expected));
+
+        // SELECT 中没有对应的表达式时，key 使用生成的名字
+        let sql = "SELECT COUNT(*) n FROM t GROUP BY x / 10 ORDER BY x / 10 DESC";
+        let result = run(sql, df);
+        let expected = df! { "n" => [1u32, 1, 2] }.unwrap();
+        assert!(result.frame_equal(&expected));
+    }
+
+    #[test]
+    fn aggregation_without_group_by_should_work() {
+        let result = run("SELECT COUNT(*), MAX(new_cases) m FROM t", covid());
+        let expected = df! { "COUNT(*)" => [5u32], "m" => [100] }.unwrap();
+        assert!(result.frame_equal(&expected));
+    }
+
+    fn countries() -> DataFrame {
+        df! {
+            "location" => ["China", "France", "Germany"],
+            "population" => [1425, 67, 83],
+        }
+        .unwrap()
+    }
+
+    #[test]
+    fn inner_join_should_work() {
+        let sql = "SELECT a.location, new_cases, b.population FROM https://x.com/covid.csv a \
+            JOIN https://x.com/countries.csv b ON a.location = b.location ORDER BY population";
+        let statement = &Parser::parse_sql(&TryDialect, sql).unwrap()[0];
+        let parsed: Sql = statement.try_into().unwrap();
+        assert_eq!(
+            parsed.sources(),
+            vec!["https://x.com/covid.csv", "https://x.com/countries.csv"]
+        );
+
+        let result = run_many(sql, vec![covid(), countries()]);
+        let expected = df! {
+            "a.location" => ["France", "China"],
+            "new_cases" => [20, 10],
+            "b.population" => [67, 1425],
+        }
+        .unwrap();
+        assert!(result.frame_equal(&expected));
+    }
+
+    #[test]
+    fn table_alias_without_join_should_work() {
+        let sql = "SELECT c.location, new_cases FROM t c WHERE c.new_cases > 10 \
+            ORDER BY c.new_cases DESC";
+        let result = run(sql, covid());
+        let expected = df! {
+            "c.location" => ["World", "Japan", "France"],
+            "new_cases" => [100, 30, 20],
+        }
+        .unwrap();
+        assert!(result.frame_equal(&expected));
+
+        let sql = "SELECT c.continent, SUM(c.new_cases) total FROM t AS c \
+            GROUP BY c.continent ORDER BY total";
+        assert_eq!(run(sql, covid()).shape(), (3, 2));
+    }
+
+    #[test]
+    fn outer_joins_should_work() {
+        let sql = "SELECT b.location, population FROM t1 a LEFT JOIN t2 b \
+            ON b.location = a.location WHERE continent = 'Europe'";
+        let result = run_many(sql, vec![covid(), countries()]);
+        let expected = df! {
+            "b.location" => [Some("France"), None],
+            "population" => [Some(67), None],
+        }
+        .unwrap();
+        assert!(result.frame_equal_missing(&expected));
+
+        let sql = "SELECT b.location FROM t1 a RIGHT JOIN t2 b ON a.location = b.location";
+        assert_eq!(run_many(sql, vec![covid(), countries()]).height(), 3);
+
+        let sql = "SELECT a.location FROM t1 a FULL JOIN t2 b ON a.location = b.location";
+        assert_eq!(run_many(sql, vec![covid(), countries()]).height(), 6);
+
+        let sql = "SELECT a.location, b.location FROM t1 a CROSS JOIN t2 b";
+        assert_eq!(run_many(sql, vec![covid(), countries()]).height(), 15);
+    }
+
+    #[test]
+    fn predicates_should_work() {
+        let locations = |sql: &str| {
+            let result = run(sql, covid());
+            result["location"]
+                .utf8()
+                .unwrap()
+                .into_no_null_iter()
+                .map(|v| v.to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let sql = "SELECT location FROM t WHERE location LIKE '%an%'";
+        assert_eq!(locations(sql), ["France", "Japan"]);
+        let sql = "SELECT location FROM t WHERE location NOT ILIKE 'c____'";
+        assert_eq!(locations(sql), ["France", "Japan", "Italy", "World"]);
+        let sql = "SELECT location FROM t WHERE location IN ('China', 'Italy', 'Spain')";
+        assert_eq!(locations(sql), ["China", "Italy"]);
+        let sql = "SELECT location FROM t WHERE location NOT IN ('China', 'Italy')";
+        assert_eq!(locations(sql), ["France", "Japan", "World"]);
+        let sql = "SELECT location FROM t WHERE new_cases BETWEEN 20 AND 30";
+        assert_eq!(locations(sql), ["France", "Japan"]);
+        let sql = "SELECT location FROM t WHERE continent IS NULL OR new_cases IS NULL";
+        assert_eq!(locations(sql), ["Italy", "World"]);
+        let sql = "SELECT location FROM t WHERE continent IS NOT NULL AND location REGEXP '^[CJ]'";
+        assert_eq!(locations(sql), ["China", "Japan"]);
+        let sql = "SELECT location FROM t WHERE location NOT REGEXP 'a' AND location !~* 'W'";
+        assert!(locations(sql).is_empty());
+    }
+
+    #[test]
+    fn subqueries_should_work() {
+        let tables = HashMap::from([("t".to_owned(), covid().lazy())]);
+        let locations = |sql: &str| -> Vec<String> {
+            let statement = &Parser::parse_sql(&TryDialect, sql).unwrap()[0];
+            let sql: Sql = statement.try_into().unwrap();
+            assert_eq!(sql.external_sources(), ["t"]);
+            let df = sql.build(&tables).unwrap().collect().unwrap();
+            let locations = df["location"].utf8().unwrap();
+            locations
+                .into_no_null_iter()
+                .map(|v| v.to_owned())
+                .collect()
+        };
+
+        let sql = "SELECT location FROM (SELECT * FROM t WHERE new_cases > 10) s \
+            WHERE continent IS NOT NULL ORDER BY new_cases DESC";
+        assert_eq!(locations(sql), ["Japan", "France"]);
+        let sql = "SELECT s.location AS location FROM (SELECT * FROM t WHERE new_cases > 10) s \
+            WHERE s.continent IS NOT NULL ORDER BY s.new_cases DESC";
+        assert_eq!(locations(sql), ["Japan", "France"]);
+        let sql = "SELECT location FROM t \
+            WHERE new_cases IN (SELECT MAX(new_cases) FROM t GROUP BY continent) \
+            AND continent IS NOT NULL";
+        assert_eq!(locations(sql), ["France", "Japan"]);
+        let sql = "SELECT location FROM t WHERE location NOT IN \
+            (SELECT location FROM t WHERE continent = 'Asia') AND new_cases > 0";
+        assert_eq!(locations(sql), ["France", "World"]);
+
+        // null 不能匹配任何值：IN 时两边的 null 都不匹配，NOT IN 的子查询中有 null 时结果为空
+        let sql = "SELECT location FROM t WHERE continent IN \
+            (SELECT continent FROM t WHERE location = 'World')";
+        assert!(locations(sql).is_empty());
+        let sql = "SELECT location FROM t WHERE location NOT IN (SELECT continent FROM t)";
+        assert!(locations(sql).is_empty());
+        let sql = "SELECT location FROM t WHERE continent NOT IN \
+            (SELECT continent FROM t WHERE continent = 'Asia')";
+        assert_eq!(locations(sql), ["France", "Italy"]);
+        let sql = "SELECT location FROM t WHERE continent NOT IN \
+            (SELECT continent FROM t WHERE new_cases > 1000)";
+        assert_eq!(locations(sql).len(), 5);
+
+        // 子查询必须只返回一列，并且 IN 子查询只能出现在 AND 连接的条件中
+        for sql in [
+            "SELECT location FROM t WHERE location IN (SELECT * FROM t)",
+            "SELECT location FROM t WHERE new_cases > 20 OR location IN (SELECT location FROM t)",
+        ] {
+            let statement = &Parser::parse_sql(&TryDialect, sql).unwrap()[0];
+            let sql: Result<Sql> = statement.try_into();
+            assert!(sql.and_then(|sql| sql.build(&tables)).is_err());
+        }
+    }
+
+    #[test]
+    fn like_to_regex_should_escape() {
+        assert_eq!(like_to_regex("a.b%", None, false), "(?s)^a\\.b.*$");
+        assert_eq!(like_to_regex("10!%_", Some('!'), true), "(?is)^10%.$");
+    }
+
+    #[test]
+    fn ambiguous_column_should_fail() {
+        let sql = "SELECT location FROM t1 a JOIN t2 b ON a.location = b.location";
+        let statement = &Parser::parse_sql(&TryDialect, sql).unwrap()[0];
+        let sql: Sql = statement.try_into().unwrap();
+        assert!(sql.apply(vec![covid().lazy(), countries().lazy()]).is_err());
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    fs::File,
+    io::{self, Cursor, Read, Seek, Write},
+    path::Path,
+};
+
+use flate2::read::MultiGzDecoder;
+use tempfile::TempPath;
+use zip::ZipArchive;
+
+use crate::{
+    error::{invalid, Result},
+    fetcher::Content,
+};
+
+/// 目前支持的压缩格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Zip,
+}
+
+impl Compression {
+    /// 先看 source 的扩展名，再看 Content-Encoding，都没有时表示没有压缩
+    pub fn detect(source: &str, content_encoding: Option<&str>) -> Option<Self> {
+        Compression::from_extension(source)
+            .or_else(|| content_encoding.and_then(Self::from_encoding))
+    }
+
+    /// 从 source 的扩展名中推断压缩格式，比如 `data.csv.gz`、`archive.zip#data.csv`
+    pub fn from_extension(source: &str) -> Option<Self> {
+        let path = source.split(['?', '#']).next().unwrap_or(source);
+        let (_, ext) = path.rsplit_once('.')?;
+        Self::from_ext(ext)
+    }
+
+    /// 从 http 响应的 Content-Encoding 中推断压缩格式
+    pub fn from_encoding(encoding: &str) -> Option<Self> {
+        match encoding.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Compression::Gzip),
+            "zstd" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn from_ext(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "gz" | "gzip" => Some(Compression::Gzip),
+            "zst" | "zstd" => Some(Compression::Zstd),
+            "zip" => Some(Compression::Zip),
+            _ => None,
+        }
+    }
+
+    // 把 reader 中的数据解压写入 writer，zip 时解压 member 指定的文件
+    fn unpack<R, W>(self, reader: R, member: Option<&str>, writer: &mut W) -> Result<()>
+    where
+        R: Read + Seek,
+        W: Write,
+    {
+        match self {
+            Compression::Gzip => io::copy(&mut MultiGzDecoder::new(reader), writer)?,
+            Compression::Zstd => io::copy(&mut zstd::Decoder::new(reader)?, writer)?,
+            Compression::Zip => {
+                let mut archive = ZipArchive::new(reader).map_err(zip_err)?;
+                let name = match member {
+                    Some(name) => name.to_owned(),
+                    None => only_file(&archive)?,
+                };
+                let mut file = archive
+                    .by_name(&name)
+                    .map_err(|_| invalid!("File {} is not found in zip archive", name))?;
+                io::copy(&mut file, writer)?
+            }
+        };
+        Ok(())
+    }
+}
+
+/// zip 中选中的文件：`archive.zip#data.csv` 中 `#` 之后的部分
+pub(crate) fn member(source: &str) -> Option<&str> {
+    source.split_once('#').map(|(_, member)| member)
+}
+
+/// 如果 source 是压缩过的，解压 content 中的数据
+pub(crate) fn decompress(source: &str, content: Content) -> Result<Content> {
+    let compression = Compression::detect(source, content.content_encoding.as_deref());
+    match compression {
+        Some(compression) => {
+            let mut data = Vec::new();
+            compression.unpack(Cursor::new(content.data), member(source), &mut data)?;
+            Ok(Content {
+                data,
+                content_type: content.content_type,
+                content_encoding: None,
+            })
+        }
+        None => Ok(content),
+    }
+}
+
+/// 和 decompress 一样，但是把 path 中的数据边解压边写入临时文件，不会把整个文件读入内存
+pub(crate) fn decompress_file(
+    compression: Compression,
+    source: &str,
+    path: &Path,
+) -> Result<TempPath> {
+    let mut file = tempfile::Builder::new().prefix("queryer-").tempfile()?;
+    compression.unpack(File::open(path)?, member(source), &mut file)?;
+    file.flush()?;
+    Ok(file.into_temp_path())
+}
+
+// 没有指定文件时，zip 中只能有一个文件
+fn only_file<R: Read + Seek>(archive: &ZipArchive<R>) -> Result<String> {
+    let mut files = archive.file_names().filter(|name| !name.ends_with('/'));
+    match (files.next(), files.next()) {
+        (Some(name), None) => Ok(name.to_owned()),
+        (None, _) => Err(invalid!("Zip archive is empty")),
+        _ => Err(invalid!(
+            "Zip archive has multiple files, select one with <url>#<file>"
+        )),
+    }
+}
+
+fn zip_err(e: zip::result::ZipError) -> crate::QueryError {
+    match e {
+        zip::result::ZipError::Io(e) => e.into(),
+        e => invalid!("Invalid zip archive: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::{write::GzEncoder, Compression as Level};
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::*;
+
+    const CSV: &[u8] = b"a,b\n1,x\n2,y\n";
+
+    fn content(data: Vec<u8>, content_encoding: Option<&str>) -> Content {
+        Content {
+            data,
+            content_encoding: content_encoding.map(|v| v.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Level::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            match name.ends_with('/') {
+                true => writer.add_directory(*name, FileOptions::default()).unwrap(),
+                false => {
+                    writer.start_file(*name, FileOptions::default()).unwrap();
+                    writer.write_all(data).unwrap();
+                }
+            }
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn detect_compression_should_work() {
+        let detect = Compression::detect;
+        assert_eq!(
+            detect("https://a.com/b.csv.gz", None),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(
+            detect("file:///b.csv.zst?x=1", None),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(
+            detect("https://a.com/b.zip#c.csv", None),
+            Some(Compression::Zip)
+        );
+        assert_eq!(
+            detect("https://a.com/b.csv", Some("gzip")),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(detect("https://a.com/b.csv", Some("identity")), None);
+        assert_eq!(detect("https://a.com/b.csv", None), None);
+    }
+
+    #[test]
+    fn decompress_should_work() {
+        let data = decompress("https://a.com/b.csv.gz", content(gzip(CSV), None)).unwrap();
+        assert_eq!(data.data, CSV);
+
+        let zstd = zstd::encode_all(CSV, 0).unwrap();
+        let data = decompress("https://a.com/b.csv", content(zstd, Some("zstd"))).unwrap();
+        assert_eq!(data.data, CSV);
+        assert!(data.content_encoding.is_none());
+
+        let data = decompress("https://a.com/b.csv", content(CSV.to_vec(), None)).unwrap();
+        assert_eq!(data.data, CSV);
+    }
+
+    #[test]
+    fn decompress_zip_should_select_member() {
+        let single = zip(&[("data.csv", CSV)]);
+        let data = decompress("file:///a.zip", content(single, None)).unwrap();
+        assert_eq!(data.data, CSV);
+
+        let multiple = zip(&[("dir/", b""), ("a.csv", b"a\n1\n"), ("dir/b.csv", CSV)]);
+        let data = decompress("file:///a.zip#dir/b.csv", content(multiple.clone(), None));
+        assert_eq!(data.unwrap().data, CSV);
+        assert!(decompress("file:///a.zip", content(multiple.clone(), None)).is_err());
+        assert!(decompress("file:///a.zip#c.csv", content(multiple, None)).is_err());
+        assert!(decompress("file:///a.zip", content(CSV.to_vec(), None)).is_err());
+    }
+
+    #[test]
+    fn decompress_file_should_work() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&gzip(CSV)).unwrap();
+        let path = decompress_file(Compression::Gzip, "file:///a.csv.gz", file.path()).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), CSV);
+    }
+}
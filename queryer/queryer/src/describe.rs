@@ -0,0 +1,77 @@
+use polars::prelude::*;
+
+use crate::{load_source, DataSet, Result};
+
+// 每列最多展示的样例数据个数
+const SAMPLE_SIZE: usize = 3;
+
+/// 获取 source 对应数据的结构：列名、推断出的类型、null 的个数以及几条样例数据
+pub async fn describe<T: AsRef<str>>(source: T) -> Result<DataSet> {
+    let ds = load_source(source).await?;
+    Ok(DataSet(describe_frame(&ds)?))
+}
+
+/// 生成 DataFrame 的结构描述，每一列对应结果中的一行
+pub(crate) fn describe_frame(df: &DataFrame) -> Result<DataFrame> {
+    let columns = df.get_columns();
+    let mut names = Vec::with_capacity(columns.len());
+    let mut dtypes = Vec::with_capacity(columns.len());
+    let mut null_counts = Vec::with_capacity(columns.len());
+    let mut samples = Vec::with_capacity(columns.len());
+
+    for column in columns {
+        names.push(column.name().to_owned());
+        dtypes.push(column.dtype().to_string());
+        null_counts.push(column.null_count() as u32);
+
+        let values = column.drop_nulls().head(Some(SAMPLE_SIZE));
+        let sample = values
+            .iter()
+            .map(|v| match v {
+                AnyValue::Utf8(v) => v.to_owned(),
+                v => v.to_string(),
+            })
+            .collect::<Vec<_>>();
+        samples.push(sample.join(", "));
+    }
+
+    Ok(df! {
+        "column" => names,
+        "dtype" => dtypes,
+        "null_count" => null_counts,
+        "sample" => samples,
+    }?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_frame_should_work() {
+        let df = df! {
+            "name" => [Some("China"), None, Some("Japan")],
+            "value" => [1, 2, 3],
+        }
+        .unwrap();
+        let result = describe_frame(&df).unwrap();
+        let expected = df! {
+            "column" => ["name", "value"],
+            "dtype" => ["str", "i32"],
+            "null_count" => [1u32, 0],
+            "sample" => ["China, Japan", "1, 2, 3"],
+        }
+        .unwrap();
+        assert!(result.frame_equal(&expected));
+    }
+
+    #[tokio::test]
+    async fn describe_should_work() {
+        let url = format!("file://{}/fixtures/covid.csv", env!("CARGO_MANIFEST_DIR"));
+        let ds = describe(url).await.unwrap();
+        assert_eq!(ds.height(), 9);
+        assert_eq!(ds["column"].get(1).unwrap(), AnyValue::Utf8("continent"));
+        assert_eq!(ds["null_count"].get(1).unwrap(), AnyValue::UInt32(1));
+        assert_eq!(ds["dtype"].get(4).unwrap(), AnyValue::Utf8("i64"));
+    }
+}
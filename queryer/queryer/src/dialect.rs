@@ -1,28 +1,91 @@
-use sqlparser::dialect::Dialect;
-
+use sqlparser::{
+    ast::{BinaryOperator, Expr},
+    dialect::Dialect,
+    keywords::Keyword,
+    parser::{Parser, ParserError},
+    tokenizer::Token,
+};
 
 #[derive(Debug, Default)]
 pub struct TryDialect;
 
 // 创建自己的 sql 方言。 TryDialect 支持 identifier 可以是简单的 url
-impl Dialect for TryDialect{
+impl Dialect for TryDialect {
     fn is_identifier_start(&self, ch: char) -> bool {
-        ('a'..='z').contains(&ch) || ('A'..='Z').contains(&ch) || ch == '_'
+        ch.is_ascii_lowercase() || ch.is_ascii_uppercase() || ch == '_'
     }
 
     // indenifier 可以有 ':', '/', '?', '&', '=' (主要目的让sql支持url)
+    // 以及 '#'，用来选择 zip 中的文件，比如 archive.zip#data.csv
     fn is_identifier_part(&self, ch: char) -> bool {
-        ('a'..='z').contains(&ch)
-            || ('A'..='Z').contains(&ch)
-            || ('0'..='9').contains(&ch)
-            || [':', '/', '?', '&', '=', '-', '_', '.'].contains(&ch)
+        ch.is_ascii_lowercase()
+            || ch.is_ascii_uppercase()
+            || ch.is_ascii_digit()
+            || [':', '/', '?', '&', '=', '-', '_', '.', '#'].contains(&ch)
+    }
+
+    // sqlparser 不认识 REGEXP / RLIKE，这里给它们和 LIKE 一样的优先级
+    fn get_next_precedence(&self, parser: &Parser) -> Option<Result<u8, ParserError>> {
+        regexp_negated(parser).map(|_| Ok(LIKE_PREC))
+    }
+
+    // `a [NOT] REGEXP 'pattern'` 解析成 postgres 风格的 `a ~ 'pattern'` / `a !~ 'pattern'`
+    fn parse_infix(
+        &self,
+        parser: &mut Parser,
+        expr: &Expr,
+        _precedence: u8,
+    ) -> Option<Result<Expr, ParserError>> {
+        let negated = regexp_negated(parser)?;
+        if negated {
+            parser.next_token();
+        }
+        parser.next_token();
+
+        let op = if negated {
+            BinaryOperator::PGRegexNotMatch
+        } else {
+            BinaryOperator::PGRegexMatch
+        };
+        Some(
+            parser
+                .parse_subexpr(LIKE_PREC)
+                .map(|pattern| Expr::BinaryOp {
+                    left: Box::new(expr.clone()),
+                    op,
+                    right: Box::new(pattern),
+                }),
+        )
+    }
+}
+
+// 和 sqlparser 中 LIKE 的优先级保持一致
+const LIKE_PREC: u8 = 19;
+
+// 接下来是 REGEXP 时返回 Some(false)，NOT REGEXP 时返回 Some(true)
+fn regexp_negated(parser: &Parser) -> Option<bool> {
+    match parser.peek_token().token {
+        Token::Word(w) if w.keyword == Keyword::NOT => {
+            is_regexp(&parser.peek_nth_token(1).token).then_some(true)
+        }
+        token => is_regexp(&token).then_some(false),
+    }
+}
+
+fn is_regexp(token: &Token) -> bool {
+    match token {
+        Token::Word(w) => {
+            let value = w.value.to_ascii_lowercase();
+            w.quote_style.is_none() && (value == "regexp" || value == "rlike")
+        }
+        _ => false,
     }
 }
 
 /// 测试辅助函数
 pub fn example_sql() -> String {
     let url = "https://raw.githubusercontent.com/owid/covid-19-data/master/public/data/latest/owid-covid-latest.csv";
-    
+
     let sql = format!(
         "SELECT location name, total_cases, new_cases, total_deaths, new_deaths \
         FROM {} where new_deaths >= 500 ORDER BY new_cases DESC LIMIT 6 OFFSET 5",
@@ -39,7 +102,25 @@ mod tests {
 
     #[test]
     fn it_works() {
-        let p = Parser::parse_sql(&TryDialect::default(), &example_sql()).unwrap();
+        let p = Parser::parse_sql(&TryDialect, &example_sql()).unwrap();
         println!("{:?}", p);
     }
+
+    #[test]
+    fn zip_member_should_be_part_of_identifier() {
+        let sql = "SELECT a FROM https://a.com/archive.zip#dir/data.csv WHERE a = 1";
+        let p = Parser::parse_sql(&TryDialect, sql).unwrap();
+        assert!(p[0]
+            .to_string()
+            .contains("FROM https://a.com/archive.zip#dir/data.csv WHERE"));
+    }
+
+    #[test]
+    fn regexp_should_be_parsed_as_regex_match() {
+        let sql = "SELECT a FROM t WHERE a REGEXP '^C' AND b NOT RLIKE 'x$'";
+        let p = Parser::parse_sql(&TryDialect, sql).unwrap();
+        let sql = p[0].to_string();
+        assert!(sql.contains("a ~ '^C'"));
+        assert!(sql.contains("b !~ 'x$'"));
+    }
 }
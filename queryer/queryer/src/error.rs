@@ -0,0 +1,174 @@
+use std::{fmt, io, string::FromUtf8Error};
+
+use polars::prelude::PolarsError;
+use sqlparser::parser::ParserError;
+use thiserror::Error;
+
+pub type Result<T, E = QueryError> = std::result::Result<T, E>;
+
+/// queryer 中所有的错误
+#[derive(Debug, Error)]
+pub enum QueryError {
+    /// SQL 语法错误，line 和 column 从 1 开始，sqlparser 没有给出位置时为 0
+    #[error("Failed to parse SQL{}: {message}", location(*.line, *.column))]
+    Parse {
+        message: String,
+        line: u64,
+        column: u64,
+    },
+    /// 不支持的 SQL 语法，node 是 sqlparser 中对应的 AST 节点，比如 `Statement::Insert`
+    #[error("{message}")]
+    Unsupported { node: String, message: String },
+    /// 语法支持，但是无法执行：表或者列不存在、函数参数不对等
+    #[error("{0}")]
+    Invalid(String),
+    /// 获取数据失败，status 是 http 响应的状态码
+    #[error("Failed to fetch {url}: {message}")]
+    Fetch {
+        url: String,
+        status: Option<u16>,
+        message: String,
+    },
+    /// polars 执行出错
+    #[error(transparent)]
+    Execution(#[from] PolarsError),
+    /// catalog、缓存元信息等配置解析出错
+    #[error("{0}")]
+    Config(String),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// 构造 `QueryError::Unsupported`，用法同 `format!`，第一个参数是 AST 节点的名字
+macro_rules! unsupported {
+    ($node:expr, $($arg:tt)*) => {
+        $crate::error::QueryError::Unsupported {
+            node: $node.into(),
+            message: format!($($arg)*),
+        }
+    };
+}
+
+/// 构造 `QueryError::Invalid`，用法同 `format!`
+macro_rules! invalid {
+    ($($arg:tt)*) => {
+        $crate::error::QueryError::Invalid(format!($($arg)*))
+    };
+}
+
+pub(crate) use {invalid, unsupported};
+
+/// AST 节点的名字，比如 `node_name("Expr", &expr)` 返回 `Expr::Subquery`
+pub(crate) fn node_name<T: fmt::Debug>(kind: &str, node: &T) -> String {
+    let debug = format!("{:?}", node);
+    let variant = debug
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .next()
+        .unwrap_or_default();
+    format!("{}::{}", kind, variant)
+}
+
+fn location(line: u64, column: u64) -> String {
+    match line {
+        0 => String::new(),
+        _ => format!(" at line {}, column {}", line, column),
+    }
+}
+
+/// sqlparser 把位置放在错误信息的最后：`... at Line: 1, Column 8`
+impl From<ParserError> for QueryError {
+    fn from(e: ParserError) -> Self {
+        let message = match e {
+            ParserError::TokenizerError(s) | ParserError::ParserError(s) => s,
+            ParserError::RecursionLimitExceeded => "recursion limit exceeded".to_owned(),
+        };
+        let parsed = message
+            .rsplit_once(" at Line: ")
+            .and_then(|(message, loc)| {
+                let (line, column) = loc.split_once(", Column ")?;
+                Some((message, line.parse().ok()?, column.parse().ok()?))
+            });
+        match parsed {
+            Some((message, line, column)) => Self::Parse {
+                message: message.to_owned(),
+                line,
+                column,
+            },
+            None => Self::Parse {
+                message,
+                line: 0,
+                column: 0,
+            },
+        }
+    }
+}
+
+impl From<reqwest::Error> for QueryError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Fetch {
+            url: e.url().map(|v| v.to_string()).unwrap_or_default(),
+            status: e.status().map(|v| v.as_u16()),
+            message: e.to_string(),
+        }
+    }
+}
+
+impl From<toml::de::Error> for QueryError {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Config(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for QueryError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Config(e.to_string())
+    }
+}
+
+impl From<FromUtf8Error> for QueryError {
+    fn from(e: FromUtf8Error) -> Self {
+        Self::Io(io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlparser::parser::Parser;
+
+    use super::*;
+    use crate::{Sql, TryDialect};
+
+    #[test]
+    fn parse_error_should_have_location() {
+        let err: QueryError = Parser::parse_sql(&TryDialect, "SELECT a FROM t\nWHERE a = = 1")
+            .unwrap_err()
+            .into();
+        assert!(err
+            .to_string()
+            .starts_with("Failed to parse SQL at line 2, column 11: "));
+        match err {
+            QueryError::Parse { line, column, .. } => assert_eq!((line, column), (2, 11)),
+            e => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn unsupported_should_name_the_node() {
+        let node = |sql: &str| {
+            let statement = &Parser::parse_sql(&TryDialect, sql).unwrap()[0];
+            match Sql::try_from(statement) {
+                Err(QueryError::Unsupported { node, .. }) => node,
+                _ => panic!("{} should be unsupported", sql),
+            }
+        };
+        assert_eq!(node("INSERT INTO t VALUES (1)"), "Statement::Insert");
+        assert_eq!(
+            node("SELECT a FROM t UNION SELECT a FROM s"),
+            "SetExpr::SetOperation"
+        );
+        assert_eq!(
+            node("SELECT EXISTS (SELECT a FROM t) FROM t"),
+            "Expr::Exists"
+        );
+    }
+}
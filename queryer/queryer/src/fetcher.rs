@@ -0,0 +1,350 @@
+use async_trait::async_trait;
+use reqwest::{header, StatusCode};
+use tokio::{fs, io::AsyncWriteExt};
+use tracing::warn;
+
+use crate::{
+    cache::{self, Cache, Meta},
+    error::{invalid, QueryError, Result},
+};
+
+// Fetch trait: 未来如果要支持更多的数据源（比如 s3），只需要实现它即可
+#[async_trait]
+pub trait Fetch {
+    type Error;
+
+    // 从数据源中获取原始数据
+    async fn fetch(&self) -> Result<Content, Self::Error>;
+}
+
+/// 从数据源获取到的原始数据
+#[derive(Debug, Default)]
+pub struct Content {
+    pub data: Vec<u8>,
+    // 数据源声明的数据类型，比如 http 响应的 Content-Type
+    pub content_type: Option<String>,
+    // http 响应的 Content-Encoding，数据被压缩过时用来选择解压方式
+    pub content_encoding: Option<String>,
+}
+
+/// 通过 http / https 获取数据
+pub struct UrlFetcher<'a>(pub &'a str);
+
+/// 带本地磁盘缓存的 http / https fetcher，缓存过期后用 ETag / Last-Modified 重新验证
+pub struct CachedFetcher<'a> {
+    pub url: &'a str,
+    pub cache: &'a Cache,
+}
+
+/// 从本地文件（file://<path>）获取数据
+pub struct FileFetcher<'a>(pub &'a str);
+
+/// 根据 source 的 scheme 选择对应的 fetcher 获取数据
+pub async fn retrieve_data(source: impl AsRef<str>) -> Result<Content> {
+    let name = source.as_ref();
+    let scheme = match name.split_once("://") {
+        Some((scheme, _)) => scheme,
+        None => return Err(invalid!("Source {} has no scheme", name)),
+    };
+
+    match scheme {
+        "http" | "https" => match cache::cache() {
+            Some(cache) => {
+                CachedFetcher {
+                    url: name,
+                    cache: &cache,
+                }
+                .fetch()
+                .await
+            }
+            None => UrlFetcher(name).fetch().await,
+        },
+        "file" => FileFetcher(name).fetch().await,
+        _ => Err(invalid!("We only support http/https/file at the moment")),
+    }
+}
+
+#[async_trait]
+impl<'a> Fetch for UrlFetcher<'a> {
+    type Error = QueryError;
+
+    async fn fetch(&self) -> Result<Content, Self::Error> {
+        let resp = reqwest::get(self.0).await?.error_for_status()?;
+        let content_type = header_value(&resp, header::CONTENT_TYPE);
+        let content_encoding = header_value(&resp, header::CONTENT_ENCODING);
+        let data = resp.bytes().await?.to_vec();
+        Ok(Content {
+            data,
+            content_type,
+            content_encoding,
+        })
+    }
+}
+
+#[async_trait]
+impl<'a> Fetch for CachedFetcher<'a> {
+    type Error = QueryError;
+
+    async fn fetch(&self) -> Result<Content, Self::Error> {
+        match self.revalidate().await? {
+            Revalidated::Cached(meta) => self.cache.content(meta).await,
+            Revalidated::Fetched(content) => Ok(content),
+        }
+    }
+}
+
+/// 重新验证之后缓存的状态
+pub(crate) enum Revalidated {
+    // 缓存的数据仍然可以使用
+    Cached(Meta),
+    // 从服务器获取了新的数据，并已写入缓存
+    Fetched(Content),
+}
+
+impl<'a> CachedFetcher<'a> {
+    /// 检查缓存是否可用：在有效期内直接使用，过期后向服务器重新验证
+    pub(crate) async fn revalidate(&self) -> Result<Revalidated> {
+        let mut req = reqwest::Client::new().get(self.url);
+        let mut meta = match self.cache.get(self.url).await {
+            Some(meta) => meta,
+            None => return Ok(Revalidated::Fetched(self.store(req.send().await?).await?)),
+        };
+        if self.cache.is_fresh(&meta) {
+            return Ok(Revalidated::Cached(meta));
+        }
+
+        if let Some(etag) = &meta.etag {
+            req = req.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &meta.last_modified {
+            req = req.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+        let resp = match req.send().await {
+            Ok(resp) => resp,
+            // 服务器连不上时使用过期的缓存，这样离线也可以查询
+            Err(e) => {
+                warn!("failed to revalidate {}, use stale cache: {}", self.url, e);
+                return Ok(Revalidated::Cached(meta));
+            }
+        };
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            meta.fetched_at = cache::now();
+            self.cache.put_meta(&meta).await?;
+            return Ok(Revalidated::Cached(meta));
+        }
+        Ok(Revalidated::Fetched(self.store(resp).await?))
+    }
+
+    // 读取响应并写入缓存，写缓存失败不影响本次查询
+    async fn store(&self, resp: reqwest::Response) -> Result<Content> {
+        let resp = resp.error_for_status()?;
+        let meta = Meta {
+            url: self.url.to_owned(),
+            etag: header_value(&resp, header::ETAG),
+            last_modified: header_value(&resp, header::LAST_MODIFIED),
+            content_type: header_value(&resp, header::CONTENT_TYPE),
+            content_encoding: header_value(&resp, header::CONTENT_ENCODING),
+            fetched_at: cache::now(),
+        };
+        let data = resp.bytes().await?.to_vec();
+        if let Err(e) = self.cache.put(&meta, &data).await {
+            warn!("failed to cache {}: {}", self.url, e);
+        }
+        Ok(Content {
+            data,
+            content_type: meta.content_type,
+            content_encoding: meta.content_encoding,
+        })
+    }
+}
+
+#[async_trait]
+impl<'a> Fetch for FileFetcher<'a> {
+    type Error = QueryError;
+
+    async fn fetch(&self) -> Result<Content, Self::Error> {
+        let data = fs::read(file_path(self.0))
+            .await
+            .map_err(|e| QueryError::Fetch {
+                url: self.0.to_owned(),
+                status: None,
+                message: e.to_string(),
+            })?;
+        Ok(Content {
+            data,
+            ..Default::default()
+        })
+    }
+}
+
+/// 去掉 "file://" 前缀以及 "?format=..."、"#data.csv" 这样的参数，剩下的就是文件路径
+pub(crate) fn file_path(url: &str) -> &str {
+    let path = url.strip_prefix("file://").unwrap_or(url);
+    path.split(['?', '#']).next().unwrap_or(path)
+}
+
+/// 把 http / https 的响应边下载边写入 file，不会把整个响应读入内存
+///
+/// 返回的 Content 中只有响应的 Content-Type 和 Content-Encoding，data 为空
+pub(crate) async fn download(url: &str, file: &mut fs::File) -> Result<Content> {
+    let mut resp = reqwest::get(url).await?.error_for_status()?;
+    let content = Content {
+        data: Vec::new(),
+        content_type: header_value(&resp, header::CONTENT_TYPE),
+        content_encoding: header_value(&resp, header::CONTENT_ENCODING),
+    };
+    while let Some(chunk) = resp.chunk().await? {
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    Ok(content)
+}
+
+fn header_value(resp: &reqwest::Response, name: header::HeaderName) -> Option<String> {
+    resp.headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn file_fetcher_should_work() {
+        let source = format!("file://{}/fixtures/covid.csv", env!("CARGO_MANIFEST_DIR"));
+        let content = retrieve_data(source).await.unwrap();
+        assert!(content.data.starts_with(b"iso_code,continent,location"));
+        assert!(content.content_type.is_none());
+    }
+
+    #[tokio::test]
+    async fn file_fetcher_should_ignore_query_string() {
+        let source = format!(
+            "file://{}/fixtures/covid.csv?format=csv",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let content = retrieve_data(source).await.unwrap();
+        assert!(content.data.starts_with(b"iso_code,continent,location"));
+    }
+
+    // 一个简单的 http server：请求带着匹配的 If-None-Match 时返回 304，否则返回数据
+    async fn serve(hits: Arc<AtomicUsize>, not_modified: Arc<AtomicUsize>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let body = "a,b\n1,2\n";
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                hits.fetch_add(1, Ordering::SeqCst);
+                let mut buf = vec![0; 4096];
+                let n = socket.read(&mut buf).await.unwrap();
+                let req = String::from_utf8_lossy(&buf[..n]).to_ascii_lowercase();
+                let resp = if req.contains("if-none-match: \"v1\"") {
+                    not_modified.fetch_add(1, Ordering::SeqCst);
+                    "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n"
+                        .to_string()
+                } else {
+                    format!(
+                        "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Type: text/csv\r\n\
+                        Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                };
+                socket.write_all(resp.as_bytes()).await.unwrap();
+            }
+        });
+        format!("http://{}/data", addr)
+    }
+
+    #[tokio::test]
+    async fn cached_fetcher_should_revalidate_with_etag() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let not_modified = Arc::new(AtomicUsize::new(0));
+        let url = serve(hits.clone(), not_modified.clone()).await;
+        let dir = tempfile::tempdir().unwrap();
+
+        // ttl 为 0 时每次都要重新验证
+        let cache = Cache::new(dir.path()).with_ttl(Duration::ZERO);
+        let fetcher = CachedFetcher {
+            url: &url,
+            cache: &cache,
+        };
+        for _ in 0..2 {
+            let content = fetcher.fetch().await.unwrap();
+            assert_eq!(content.data, b"a,b\n1,2\n");
+            assert_eq!(content.content_type.as_deref(), Some("text/csv"));
+        }
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+        assert_eq!(not_modified.load(Ordering::SeqCst), 1);
+
+        // 在有效期之内不会访问服务器
+        let cache = Cache::new(dir.path());
+        let fetcher = CachedFetcher {
+            url: &url,
+            cache: &cache,
+        };
+        assert_eq!(fetcher.fetch().await.unwrap().data, b"a,b\n1,2\n");
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn cached_fetcher_should_fallback_to_stale_cache() {
+        // 先占用一个端口再释放，保证这个地址上没有服务
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/data", listener.local_addr().unwrap());
+        drop(listener);
+
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path()).with_ttl(Duration::ZERO);
+        let meta = Meta {
+            url: url.clone(),
+            ..Default::default()
+        };
+        cache.put(&meta, b"stale").await.unwrap();
+
+        let content = CachedFetcher {
+            url: &url,
+            cache: &cache,
+        }
+        .fetch()
+        .await
+        .unwrap();
+        assert_eq!(content.data, b"stale");
+
+        cache.purge().await.unwrap();
+        let err = CachedFetcher {
+            url: &url,
+            cache: &cache,
+        }
+        .fetch()
+        .await
+        .unwrap_err();
+        assert!(matches!(err, QueryError::Fetch { url: u, status: None, .. } if u == url));
+    }
+
+    #[tokio::test]
+    async fn unknown_scheme_should_fail() {
+        assert!(retrieve_data("s3://bucket/covid.csv").await.is_err());
+        assert!(retrieve_data("covid.csv").await.is_err());
+
+        let url = "file:///not/exist.csv";
+        let err = retrieve_data(url).await.unwrap_err();
+        assert!(matches!(err, QueryError::Fetch { url: u, .. } if u == url));
+    }
+}
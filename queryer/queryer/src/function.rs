@@ -0,0 +1,598 @@
+use polars::prelude::*;
+use sqlparser::ast::{
+    DataType as SqlDataType, DateTimeField, Function as SqlFunction, FunctionArg, FunctionArgExpr,
+    TrimWhereField, WindowSpec, WindowType,
+};
+
+use crate::{
+    convert::{Expression, Order},
+    error::{invalid, node_name, unsupported, QueryError, Result},
+};
+
+// 同 convert 中一样，包装一下 SqlParser 的类型以便实现 TryFrom
+pub struct Function(pub(crate) SqlFunction);
+pub struct Argument(pub(crate) FunctionArg);
+pub struct ColumnType<'a>(pub(crate) &'a SqlDataType);
+
+/// 把 SqlParser 的 Function 转换成 DataFrame 的 Expr
+impl TryFrom<Function> for Expr {
+    type Error = QueryError;
+
+    fn try_from(f: Function) -> Result<Self, Self::Error> {
+        let SqlFunction {
+            name,
+            args,
+            over,
+            distinct,
+            ..
+        } = f.0;
+        let name = name.to_string().to_uppercase();
+
+        let mut exprs: Vec<Expr> = Vec::with_capacity(args.len());
+        for arg in args {
+            exprs.push(Argument(arg).try_into()?);
+        }
+
+        match over {
+            Some(WindowType::WindowSpec(spec)) if !distinct => return window(&name, exprs, spec),
+            Some(_) => {
+                return Err(unsupported!(
+                    "WindowType",
+                    "Window function {} is not supported",
+                    name
+                ))
+            }
+            None => {}
+        }
+
+        match name.as_str() {
+            "COUNT" | "SUM" | "AVG" | "MIN" | "MAX" => aggregate(&name, exprs, distinct),
+            _ if distinct => Err(unsupported!(
+                "Function",
+                "DISTINCT is not supported in function {}",
+                name
+            )),
+            _ => scalar(&name, exprs),
+        }
+    }
+}
+
+/// 把 SqlParser 的 FunctionArg 转换成 DataFrame 的 Expr
+impl TryFrom<Argument> for Expr {
+    type Error = QueryError;
+
+    fn try_from(arg: Argument) -> Result<Self, Self::Error> {
+        match arg.0 {
+            FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => {
+                Expression(Box::new(expr)).try_into()
+            }
+            FunctionArg::Unnamed(FunctionArgExpr::Wildcard) => Ok(col("*")),
+            arg => Err(unsupported!(
+                node_name("FunctionArg", &arg),
+                "Function argument {} is not supported",
+                arg
+            )),
+        }
+    }
+}
+
+/// 把 SqlParser 的 DataType 转换成 DataFrame 的 DataType
+impl<'a> TryFrom<ColumnType<'a>> for DataType {
+    type Error = QueryError;
+
+    fn try_from(t: ColumnType<'a>) -> Result<Self, Self::Error> {
+        match t.0 {
+            SqlDataType::Boolean => Ok(DataType::Boolean),
+            SqlDataType::SmallInt(_) | SqlDataType::Int(_) | SqlDataType::Integer(_) => {
+                Ok(DataType::Int32)
+            }
+            SqlDataType::BigInt(_) => Ok(DataType::Int64),
+            SqlDataType::Float(_) | SqlDataType::Real => Ok(DataType::Float32),
+            SqlDataType::Double
+            | SqlDataType::DoublePrecision
+            | SqlDataType::Decimal(_)
+            | SqlDataType::Numeric(_) => Ok(DataType::Float64),
+            SqlDataType::Char(_)
+            | SqlDataType::Varchar(_)
+            | SqlDataType::Text
+            | SqlDataType::String => Ok(DataType::Utf8),
+            SqlDataType::Date => Ok(DataType::Date),
+            SqlDataType::Datetime(_) | SqlDataType::Timestamp(_, _) => {
+                Ok(DataType::Datetime(TimeUnit::Microseconds, None))
+            }
+            v => Err(unsupported!(
+                node_name("DataType", v),
+                "Data type {} is not supported",
+                v
+            )),
+        }
+    }
+}
+
+/// 聚合函数
+fn aggregate(name: &str, exprs: Vec<Expr>, distinct: bool) -> Result<Expr> {
+    let [arg] = args::<1>(name, exprs)?;
+    // DISTINCT 时先去掉 null 和重复值再聚合
+    let arg = match distinct {
+        true => arg.drop_nulls().unique(),
+        false => arg,
+    };
+
+    match name {
+        "COUNT" => match arg {
+            Expr::Wildcard => Ok(count()),
+            arg => Ok(arg.drop_nulls().count()),
+        },
+        "SUM" => Ok(arg.sum()),
+        "AVG" => Ok(arg.mean()),
+        "MIN" => Ok(arg.min()),
+        _ => Ok(arg.max()),
+    }
+}
+
+/// 窗口函数：`f(...) OVER (PARTITION BY ... ORDER BY ...)`
+///
+/// 有 ORDER BY 时，先按 ORDER BY 排序计算，再把结果放回原来的顺序，
+/// 此时聚合函数计算的是从分区第一行到当前行的累计值（相当于 ROWS 而不是 RANGE）
+fn window(name: &str, exprs: Vec<Expr>, spec: WindowSpec) -> Result<Expr> {
+    if spec.window_frame.is_some() {
+        return Err(unsupported!(
+            "WindowFrame",
+            "Window frame of {} is not supported",
+            name
+        ));
+    }
+
+    let mut partition_by = Vec::with_capacity(spec.partition_by.len());
+    for expr in spec.partition_by {
+        partition_by.push(Expression(Box::new(expr)).try_into()?);
+    }
+    // 没有 PARTITION BY 时整个表是一个分区
+    if partition_by.is_empty() {
+        partition_by.push(lit(true));
+    }
+
+    let mut order_by: Vec<(Expr, bool)> = Vec::with_capacity(spec.order_by.len());
+    for expr in spec.order_by.iter() {
+        order_by.push(Order(expr).try_into()?);
+    }
+
+    let expr = match name {
+        "ROW_NUMBER" => {
+            args::<0>(name, exprs)?;
+            let key = match order_by.first() {
+                Some((key, _)) => key.clone(),
+                None => return Err(invalid!("Window function {} requires ORDER BY", name)),
+            };
+            ordered(key, &order_by, |e| e.cumcount(false) + lit(1))
+        }
+        "RANK" | "DENSE_RANK" => {
+            args::<0>(name, exprs)?;
+            let (key, descending) = match order_by.as_slice() {
+                [(key, descending)] => (key.clone(), *descending),
+                _ => {
+                    return Err(invalid!(
+                        "Window function {} requires exactly one ORDER BY expression",
+                        name
+                    ))
+                }
+            };
+            let method = match name {
+                "RANK" => RankMethod::Min,
+                _ => RankMethod::Dense,
+            };
+            key.rank(RankOptions { method, descending }, None)
+        }
+        "LAG" | "LEAD" => {
+            let (arg, offset, default) = match exprs.len() {
+                1 => {
+                    let [arg] = args::<1>(name, exprs)?;
+                    (arg, 1, None)
+                }
+                2 => {
+                    let [arg, offset] = args::<2>(name, exprs)?;
+                    (arg, literal_int(name, &offset)?, None)
+                }
+                _ => {
+                    let [arg, offset, default] = args::<3>(name, exprs)?;
+                    (arg, literal_int(name, &offset)?, Some(default))
+                }
+            };
+            let periods = if name == "LAG" { offset } else { -offset };
+            ordered(arg, &order_by, |e| match default {
+                Some(default) => e.shift_and_fill(periods, default),
+                None => e.shift(periods),
+            })
+        }
+        "COUNT" | "SUM" | "AVG" | "MIN" | "MAX" if order_by.is_empty() => {
+            aggregate(name, exprs, false)?
+        }
+        "COUNT" | "SUM" | "AVG" | "MIN" | "MAX" => {
+            // COUNT(*) 累计的是行数，用排序的第一列计数即可
+            let (arg, count_all) = match args::<1>(name, exprs)? {
+                [Expr::Wildcard] => (order_by[0].0.clone(), true),
+                [arg] => (arg, false),
+            };
+            // 累计值在 null 的位置也是 null，用前面的累计值填上
+            ordered(arg, &order_by, |e| match name {
+                "COUNT" if count_all => e.cumcount(false) + lit(1),
+                "COUNT" => e.is_not_null().cumsum(false),
+                "SUM" => e.cumsum(false).forward_fill(None),
+                "AVG" => {
+                    let count = e.clone().is_not_null().cumsum(false);
+                    e.cast(DataType::Float64).cumsum(false).forward_fill(None) / count
+                }
+                "MIN" => e.cummin(false).forward_fill(None),
+                _ => e.cummax(false).forward_fill(None),
+            })
+        }
+        _ => {
+            return Err(unsupported!(
+                "Function",
+                "Window function {} is not supported",
+                name
+            ))
+        }
+    };
+    Ok(expr.over(partition_by))
+}
+
+// 按 ORDER BY 排序后计算 f，再把结果放回原来的顺序
+fn ordered(expr: Expr, order_by: &[(Expr, bool)], f: impl FnOnce(Expr) -> Expr) -> Expr {
+    if order_by.is_empty() {
+        return f(expr);
+    }
+    // idx 是排序后每一行在原来的数据中的位置。分区中的 sort_by 不支持多列，
+    // 所以从最后一列开始依次做稳定排序
+    let mut idx = order_by[0].0.clone().cumcount(false);
+    for (key, descending) in order_by.iter().rev() {
+        let options = SortOptions {
+            descending: *descending,
+            maintain_order: true,
+            ..Default::default()
+        };
+        idx = idx.clone().take(key.clone().take(idx).arg_sort(options));
+    }
+    f(expr.take(idx.clone())).take(idx.arg_sort(Default::default()))
+}
+
+/// 标量函数
+fn scalar(name: &str, exprs: Vec<Expr>) -> Result<Expr> {
+    match name {
+        "UPPER" | "UCASE" => {
+            let [arg] = args::<1>(name, exprs)?;
+            Ok(arg.str().to_uppercase())
+        }
+        "LOWER" | "LCASE" => {
+            let [arg] = args::<1>(name, exprs)?;
+            Ok(arg.str().to_lowercase())
+        }
+        "LENGTH" | "CHAR_LENGTH" | "CHARACTER_LENGTH" => {
+            let [arg] = args::<1>(name, exprs)?;
+            Ok(arg.str().n_chars())
+        }
+        "SUBSTR" => match exprs.len() {
+            2 => {
+                let [arg, from] = args::<2>(name, exprs)?;
+                substring(arg, Some(from), None)
+            }
+            _ => {
+                let [arg, from, len] = args::<3>(name, exprs)?;
+                substring(arg, Some(from), Some(len))
+            }
+        },
+        "LTRIM" => {
+            let [arg] = args::<1>(name, exprs)?;
+            Ok(arg.str().strip_chars_start(None))
+        }
+        "RTRIM" => {
+            let [arg] = args::<1>(name, exprs)?;
+            Ok(arg.str().strip_chars_end(None))
+        }
+        "CONCAT" if !exprs.is_empty() => Ok(concat_str(exprs, "")),
+        "COALESCE" if !exprs.is_empty() => Ok(coalesce(&exprs)),
+        "ABS" => {
+            let [arg] = args::<1>(name, exprs)?;
+            Ok(arg.abs())
+        }
+        "ROUND" => match exprs.len() {
+            1 => {
+                let [arg] = args::<1>(name, exprs)?;
+                Ok(arg.cast(DataType::Float64).round(0))
+            }
+            _ => {
+                let [arg, decimals] = args::<2>(name, exprs)?;
+                let decimals = literal_int(name, &decimals)?;
+                Ok(arg.cast(DataType::Float64).round(decimals as u32))
+            }
+        },
+        "YEAR" | "MONTH" | "DAY" | "HOUR" | "MINUTE" | "SECOND" => {
+            let [arg] = args::<1>(name, exprs)?;
+            date_part(name, arg)
+        }
+        "DATE_PART" => {
+            let [field, arg] = args::<2>(name, exprs)?;
+            match field {
+                Expr::Literal(LiteralValue::Utf8(field)) => date_part(&field.to_uppercase(), arg),
+                _ => Err(invalid!("The first argument of DATE_PART must be a string")),
+            }
+        }
+        _ => Err(unsupported!(
+            "Function",
+            "Function {} is not supported",
+            name
+        )),
+    }
+}
+
+/// CAST(expr AS type)
+pub(crate) fn cast(expr: Expr, data_type: &SqlDataType) -> Result<Expr> {
+    let dtype: DataType = ColumnType(data_type).try_into()?;
+    Ok(expr.cast(dtype))
+}
+
+/// CASE [operand] WHEN ... THEN ... [ELSE ...] END
+pub(crate) fn case_when(
+    operand: Option<Expr>,
+    conditions: Vec<Expr>,
+    results: Vec<Expr>,
+    else_result: Option<Expr>,
+) -> Result<Expr> {
+    // 从最后一个 WHEN 开始，一层层嵌套成 when().then().otherwise()
+    let mut expr = else_result.unwrap_or_else(|| lit(NULL));
+    for (condition, result) in conditions.into_iter().zip(results).rev() {
+        let condition = match &operand {
+            Some(operand) => operand.clone().eq(condition),
+            None => condition,
+        };
+        expr = when(condition).then(result).otherwise(expr);
+    }
+    Ok(expr)
+}
+
+/// SUBSTRING(expr FROM start FOR len)，start 从 1 开始
+pub(crate) fn substring(expr: Expr, from: Option<Expr>, len: Option<Expr>) -> Result<Expr> {
+    let start = match from {
+        Some(from) => literal_int("SUBSTRING", &from)?,
+        None => 1,
+    };
+    let len = match len {
+        Some(len) => Some(literal_int("SUBSTRING", &len)? as u64),
+        None => None,
+    };
+    Ok(expr.str().str_slice((start - 1).max(0), len))
+}
+
+/// TRIM([BOTH | LEADING | TRAILING] [what FROM] expr)
+pub(crate) fn trim(
+    expr: Expr,
+    trim_where: Option<&TrimWhereField>,
+    trim_what: Option<Expr>,
+) -> Result<Expr> {
+    let what = match trim_what {
+        Some(Expr::Literal(LiteralValue::Utf8(s))) => Some(s),
+        Some(_) => {
+            return Err(unsupported!(
+                "Expr::Trim",
+                "TRIM only supports string literal characters"
+            ))
+        }
+        None => None,
+    };
+    match trim_where {
+        Some(TrimWhereField::Leading) => Ok(expr.str().strip_chars_start(what)),
+        Some(TrimWhereField::Trailing) => Ok(expr.str().strip_chars_end(what)),
+        _ => Ok(expr.str().strip_chars(what)),
+    }
+}
+
+/// EXTRACT(field FROM expr)
+pub(crate) fn extract(field: &DateTimeField, expr: Expr) -> Result<Expr> {
+    date_part(&field.to_string().to_uppercase(), expr)
+}
+
+// 取日期/时间中的某个部分。数据源读出来的日期往往是字符串，
+// 所以统一先转成字符串再解析成日期/时间
+fn date_part(part: &str, expr: Expr) -> Result<Expr> {
+    let options = StrptimeOptions {
+        strict: false,
+        exact: false,
+        ..Default::default()
+    };
+    let s = expr.cast(DataType::Utf8);
+    let date = || {
+        let options = StrptimeOptions {
+            format: Some("%Y-%m-%d".into()),
+            ..options.clone()
+        };
+        s.clone().str().to_date(options).dt()
+    };
+    let datetime = || {
+        s.clone()
+            .str()
+            .to_datetime(
+                Some(TimeUnit::Microseconds),
+                None,
+                options.clone(),
+                lit("raise"),
+            )
+            .dt()
+    };
+    match part {
+        "YEAR" => Ok(date().year()),
+        "MONTH" => Ok(date().month()),
+        "DAY" => Ok(date().day()),
+        "HOUR" => Ok(datetime().hour()),
+        "MINUTE" => Ok(datetime().minute()),
+        "SECOND" => Ok(datetime().second()),
+        v => Err(unsupported!(
+            "DateTimeField",
+            "Date part {} is not supported",
+            v
+        )),
+    }
+}
+
+// 检查参数个数并按数组返回，方便解构
+fn args<const N: usize>(name: &str, exprs: Vec<Expr>) -> Result<[Expr; N]> {
+    exprs
+        .try_into()
+        .map_err(|_| invalid!("Function {} expects {} argument(s)", name, N))
+}
+
+// 函数中需要整数字面量的参数，比如 SUBSTR 的位置和 ROUND 的精度
+fn literal_int(name: &str, expr: &Expr) -> Result<i64> {
+    match expr {
+        Expr::Literal(LiteralValue::Int64(n)) if *n >= 0 => Ok(*n),
+        Expr::Literal(LiteralValue::Int32(n)) if *n >= 0 => Ok(*n as i64),
+        _ => Err(invalid!("{} expects a non-negative integer literal", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlparser::parser::Parser;
+
+    use crate::{dialect::TryDialect, Sql};
+
+    use super::*;
+
+    fn run(sql: &str) -> DataFrame {
+        let df = df! {
+            "location" => ["  China ", "france"],
+            "iso_code" => [Some("CHN"), None],
+            "rate" => [-1.256, 2.5],
+            "last_updated_date" => ["2023-10-01", "2022-03-15"],
+            "updated_at" => ["2023-10-01 08:30:15", "2022-03-15 23:01:02"],
+        }
+        .unwrap();
+        let statement = &Parser::parse_sql(&TryDialect, sql).unwrap()[0];
+        let sql: Sql = statement.try_into().unwrap();
+        sql.apply(vec![df.lazy()]).unwrap().collect().unwrap()
+    }
+
+    #[test]
+    fn string_functions_should_work() {
+        let df = run("SELECT UPPER(TRIM(location)) a, LOWER(location) b, \
+            LENGTH(TRIM(LEADING ' ' FROM location)) c, SUBSTR(TRIM(location), 2, 3) d, \
+            SUBSTRING(TRIM(location) FROM 3) e, CONCAT(iso_code, '-', 'x') f FROM t");
+        let expected = df! {
+            "a" => ["CHINA", "FRANCE"],
+            "b" => ["  china ", "france"],
+            "c" => [6u32, 6],
+            "d" => ["hin", "ran"],
+            "e" => ["ina", "ance"],
+            "f" => [Some("CHN-x"), None],
+        }
+        .unwrap();
+        assert!(df.frame_equal_missing(&expected));
+    }
+
+    #[test]
+    fn unnamed_expressions_should_use_sql_as_name() {
+        let df = run("SELECT location, UPPER(location), -rate, rate * 2 FROM t");
+        assert_eq!(
+            df.get_column_names(),
+            ["location", "UPPER(location)", "-rate", "rate * 2"]
+        );
+        assert_eq!(df["-rate"].get(1).unwrap(), AnyValue::Float64(-2.5));
+    }
+
+    #[test]
+    fn numeric_functions_should_work() {
+        let df = run("SELECT ABS(rate) a, ROUND(rate, 1) b, ROUND(rate) c, \
+            CAST(rate AS INT) d, COALESCE(iso_code, location) e FROM t");
+        let expected = df! {
+            "a" => [1.256, 2.5],
+            "b" => [-1.3, 2.5],
+            "c" => [-1.0, 3.0],
+            "d" => [-1i32, 2],
+            "e" => ["CHN", "france"],
+        }
+        .unwrap();
+        assert!(df.frame_equal(&expected));
+    }
+
+    #[test]
+    fn case_when_should_work() {
+        let df = run(
+            "SELECT CASE WHEN rate > 0 THEN 'up' WHEN rate < 0 THEN 'down' END a, \
+            CASE iso_code WHEN 'CHN' THEN 1 ELSE 0 END b FROM t",
+        );
+        let expected = df! { "a" => ["down", "up"], "b" => [1i64, 0] }.unwrap();
+        assert!(df.frame_equal(&expected));
+    }
+
+    #[test]
+    fn date_parts_should_work() {
+        let df = run(
+            "SELECT YEAR(last_updated_date) y, EXTRACT(MONTH FROM last_updated_date) m, \
+            DATE_PART('day', last_updated_date) d FROM t WHERE YEAR(last_updated_date) = 2023",
+        );
+        let expected = df! { "y" => [2023i32], "m" => [10u32], "d" => [1u32] }.unwrap();
+        assert!(df.frame_equal(&expected));
+
+        let df = run("SELECT HOUR(updated_at) h, MINUTE(updated_at) m, \
+            EXTRACT(SECOND FROM updated_at) s, DAY(updated_at) d FROM t");
+        let expected = df! {
+            "h" => [8u32, 23],
+            "m" => [30u32, 1],
+            "s" => [15u32, 2],
+            "d" => [1u32, 15],
+        }
+        .unwrap();
+        assert!(df.frame_equal(&expected));
+    }
+
+    #[test]
+    fn window_functions_should_work() {
+        let df = df! {
+            "location" => ["China", "France", "China", "France", "China"],
+            "date" => ["2023-01-02", "2023-01-01", "2023-01-01", "2023-01-02", "2023-01-03"],
+            "new_cases" => [Some(5), Some(7), Some(3), None, Some(4)],
+        }
+        .unwrap();
+        let sql = "SELECT location, date, \
+            ROW_NUMBER() OVER (PARTITION BY location ORDER BY date) rn, \
+            RANK() OVER (ORDER BY new_cases DESC) r, \
+            new_cases - LAG(new_cases) OVER (PARTITION BY location ORDER BY date) delta, \
+            LEAD(new_cases, 1, 0) OVER (PARTITION BY location ORDER BY date) next, \
+            SUM(new_cases) OVER (PARTITION BY location ORDER BY date) total, \
+            MAX(new_cases) OVER (PARTITION BY location) peak, \
+            COUNT(*) OVER (ORDER BY date DESC, location) n \
+            FROM t ORDER BY location, date";
+        let statement = &Parser::parse_sql(&TryDialect, sql).unwrap()[0];
+        let sql: Sql = statement.try_into().unwrap();
+        let df = sql.apply(vec![df.lazy()]).unwrap().collect().unwrap();
+        let expected = df! {
+            "location" => ["China", "China", "China", "France", "France"],
+            "date" => ["2023-01-01", "2023-01-02", "2023-01-03", "2023-01-01", "2023-01-02"],
+            "rn" => [1u32, 2, 3, 1, 2],
+            "r" => [Some(4u32), Some(2), Some(3), Some(1), None],
+            "delta" => [None, Some(2), Some(-1), None, None],
+            "next" => [Some(5), Some(4), Some(0), None, Some(0)],
+            "total" => [3, 8, 12, 7, 7],
+            "peak" => [5, 5, 5, 7, 7],
+            "n" => [4u32, 2, 1, 5, 3],
+        }
+        .unwrap();
+        assert!(df.frame_equal_missing(&expected));
+
+        for sql in [
+            "SELECT RANK() OVER (PARTITION BY location) FROM t",
+            "SELECT SUM(new_cases) OVER (ORDER BY date ROWS 1 PRECEDING) FROM t",
+            "SELECT NTILE(2) OVER (ORDER BY date) FROM t",
+        ] {
+            let statement = &Parser::parse_sql(&TryDialect, sql).unwrap()[0];
+            assert!(Sql::try_from(statement).is_err());
+        }
+    }
+
+    #[test]
+    fn unsupported_function_should_fail() {
+        let sql = "SELECT MD5(location) FROM t";
+        let statement = &Parser::parse_sql(&TryDialect, sql).unwrap()[0];
+        let err = Sql::try_from(statement).err().unwrap();
+        assert_eq!(err.to_string(), "Function MD5 is not supported");
+    }
+}
@@ -1,17 +1,337 @@
-mod dialect;
+use std::{
+    io,
+    ops::{Deref, DerefMut},
+};
+
+use polars::prelude::*;
+use tracing::info;
+
+mod cache;
+mod catalog;
 mod convert;
+mod decompress;
+mod describe;
+mod dialect;
+mod error;
+mod fetcher;
+mod function;
+mod loader;
+mod params;
+mod session;
+mod streaming;
+
+pub use cache::{cache, set_cache, Cache};
+pub use catalog::{Catalog, TableSource};
+pub use convert::{Join, JoinKind, Sql, Table};
+pub use decompress::Compression;
+pub use describe::describe;
+pub use dialect::{example_sql, TryDialect};
+pub use error::{QueryError, Result};
+pub use fetcher::{retrieve_data, CachedFetcher, Content, Fetch, FileFetcher, UrlFetcher};
+pub use loader::{detect_content, Format, Load, LoadOptions, Loader};
+pub use params::Value;
+pub use session::Session;
+
+/// 查询的结果，对 DataFrame 的简单封装
+#[derive(Debug)]
+pub struct DataSet(DataFrame);
+
+/// 让 DataSet 用起来和 DataFrame 一致
+impl Deref for DataSet {
+    type Target = DataFrame;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// 让 DataSet 用起来和 DataFrame 一致
+impl DerefMut for DataSet {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// 取出 DataSet 中的 DataFrame，给绑定到其它语言时使用
+impl From<DataSet> for DataFrame {
+    fn from(ds: DataSet) -> Self {
+        ds.0
+    }
+}
+
+impl DataSet {
+    /// 从 DataSet 转换成 csv
+    pub fn to_csv(&mut self) -> Result<String> {
+        let mut buf = Vec::new();
+        CsvWriter::new(&mut buf).finish(self)?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    /// 从 DataSet 转换成 json 数组
+    pub fn to_json(&mut self) -> Result<String> {
+        let mut buf = Vec::new();
+        JsonWriter::new(&mut buf)
+            .with_json_format(JsonFormat::Json)
+            .finish(self)?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    /// 从 DataSet 转换成 ndjson，每行一条记录
+    pub fn to_ndjson(&mut self) -> Result<String> {
+        let mut buf = Vec::new();
+        JsonWriter::new(&mut buf)
+            .with_json_format(JsonFormat::JsonLines)
+            .finish(self)?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    /// 从 DataSet 转换成 markdown 表格
+    pub fn to_markdown(&self) -> Result<String> {
+        let names = self.get_column_names();
+        let mut md = format!("| {} |\n", names.join(" | "));
+        md.push_str(&format!("|{}\n", " --- |".repeat(names.len())));
+
+        for i in 0..self.height() {
+            let mut row = Vec::with_capacity(names.len());
+            for column in self.get_columns() {
+                row.push(markdown_cell(column.get(i)?));
+            }
+            md.push_str(&format!("| {} |\n", row.join(" | ")));
+        }
+
+        Ok(md)
+    }
+
+    /// 从 DataSet 转换成 parquet
+    pub fn to_parquet(&mut self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        ParquetWriter::new(&mut buf).finish(self)?;
+        Ok(buf)
+    }
+
+    /// 从 DataSet 转换成 Arrow IPC 文件格式
+    pub fn to_ipc(&mut self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        IpcWriter::new(&mut buf).finish(self)?;
+        Ok(buf)
+    }
+}
+
+// markdown 表格中的单元格：字符串不加引号，null 留空，'|' 需要转义
+fn markdown_cell(value: AnyValue) -> String {
+    let cell = match value {
+        AnyValue::Null => String::new(),
+        AnyValue::Utf8(v) => v.to_owned(),
+        v => v.to_string(),
+    };
+    cell.replace('|', "\\|")
+}
+
+/// 从 source 中获取数据，并按照检测到的格式加载成 DataSet
+pub async fn load_source<T: AsRef<str>>(source: T) -> Result<DataSet> {
+    load_table(&TableSource::new(source.as_ref())).await
+}
+
+/// 按照 table 中的格式和选项获取数据，并加载成 DataSet
+pub async fn load_table(table: &TableSource) -> Result<DataSet> {
+    let df = scan_table(table).await?;
+    blocking(move || Ok(DataSet(df.collect()?))).await
+}
+
+/// 从 source 中获取数据生成 LazyFrame
+pub async fn scan_source<T: AsRef<str>>(source: T) -> Result<LazyFrame> {
+    scan_table(&TableSource::new(source.as_ref())).await
+}
+
+/// 按照 table 中的格式和选项获取数据生成 LazyFrame
+///
+/// 开启缓存时，http / https 数据源解析后会以 parquet 格式缓存，之后用 scan_parquet 读取
+pub async fn scan_table(table: &TableSource) -> Result<LazyFrame> {
+    let url = table.url.as_str();
+    info!("retrieving data from source: {}", url);
+    if url.starts_with("http://") || url.starts_with("https://") {
+        if let Some(cache) = cache() {
+            return cache.scan(table).await;
+        }
+    }
+    let content = retrieve_data(url).await?;
+    let table = table.clone();
+    Ok(blocking(move || table.loader(content)?.load())
+        .await?
+        .0
+        .lazy())
+}
+
+// 解压、解析、执行查询等 CPU 密集的工作放到 tokio 的阻塞线程池中执行，不阻塞异步任务
+pub(crate) async fn blocking<F, T>(f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(io::Error::from)?
+}
 
-pub fn add(left: usize, right: usize) -> usize {
-    left + right
+/// 从 from 中获取数据，从 where 中过滤，最后选取需要返回的列
+pub async fn query<T: AsRef<str>>(sql: T) -> Result<DataSet> {
+    query_with_catalog(sql, &Catalog::default()).await
+}
+
+/// 和 query 一样，但先把 SQL 中的 `?` / `$1` 占位符替换成 params 中对应的参数
+pub async fn query_with_params<T: AsRef<str>>(sql: T, params: &[Value]) -> Result<DataSet> {
+    Session::default().query_with_params(sql, params).await
+}
+
+/// 和 query 一样，但 FROM 中的表名会先在 catalog 中查找，比如 `SELECT * FROM covid`
+pub async fn query_with_catalog<T: AsRef<str>>(sql: T, catalog: &Catalog) -> Result<DataSet> {
+    Session::new(catalog.clone()).query(sql).await
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn query_should_reject_multiple_statements() {
+        let result = query("SELECT a FROM t1; SELECT b FROM t2").await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn query_should_reject_non_select_statement() {
+        let result = query("DELETE FROM t1 WHERE a = 1").await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn query_local_file_should_work() {
+        let sql = format!(
+            "SELECT location name, new_deaths FROM file://{}/fixtures/covid.csv \
+            WHERE new_deaths >= 500 ORDER BY new_deaths DESC LIMIT 3 OFFSET 1",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let df = query(sql).await.unwrap();
+        assert_eq!(df.get_column_names(), &["name", "new_deaths"]);
+        assert_eq!(df.height(), 3);
+        assert_eq!(df["name"].get(0).unwrap(), AnyValue::Utf8("United States"));
+    }
+
+    #[tokio::test]
+    async fn query_with_catalog_should_work() {
+        let url = format!("file://{}/fixtures/covid.csv", env!("CARGO_MANIFEST_DIR"));
+        let catalog = Catalog::new().with_table("covid", TableSource::new(url));
+        let sql = "SELECT location FROM covid WHERE iso_code = 'FRA'";
+        let df = query_with_catalog(sql, &catalog).await.unwrap();
+        assert_eq!(df["location"].get(0).unwrap(), AnyValue::Utf8("France"));
+
+        let df = query_with_catalog("DESCRIBE covid", &catalog)
+            .await
+            .unwrap();
+        assert_eq!(df.height(), 9);
+
+        assert!(query("SELECT * FROM covid").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn query_with_params_should_work() {
+        let sql = format!(
+            "SELECT location FROM file://{}/fixtures/covid.csv \
+            WHERE continent = ? AND new_cases > ? ORDER BY location LIMIT ?",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let params = ["Europe".into(), 20000.into(), 1.into()];
+        let df = query_with_params(&sql, &params).await.unwrap();
+        assert_eq!(df.height(), 1);
+        assert_eq!(df["location"].get(0).unwrap(), AnyValue::Utf8("Italy"));
+
+        // 参数不会被当作 SQL 解析
+        let params = ["Europe' OR '1' = '1".into(), 0.into(), 10.into()];
+        let df = query_with_params(&sql, &params).await.unwrap();
+        assert_eq!(df.height(), 0);
+        assert!(query_with_params(&sql, &[]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn load_source_should_work() {
+        let url = format!("file://{}/fixtures/covid.csv", env!("CARGO_MANIFEST_DIR"));
+        let ds = load_source(url).await.unwrap();
+        assert_eq!(ds.width(), 9);
+        assert_eq!(ds.get_column_names()[0], "iso_code");
+    }
+
+    #[tokio::test]
+    async fn describe_statement_should_work() {
+        let sql = format!(
+            "DESCRIBE file://{}/fixtures/covid.csv",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let df = query(sql).await.unwrap();
+        assert_eq!(
+            df.get_column_names(),
+            &["column", "dtype", "null_count", "sample"]
+        );
+        assert_eq!(df.height(), 9);
+    }
+
+    #[tokio::test]
+    async fn query_join_should_work() {
+        let url = format!("file://{}/fixtures/covid.csv", env!("CARGO_MANIFEST_DIR"));
+        let sql = format!(
+            "SELECT a.location, b.new_deaths FROM {url} a JOIN {url} b \
+            ON a.iso_code = b.iso_code WHERE a.new_deaths >= 1000"
+        );
+        let df = query(sql).await.unwrap();
+        assert_eq!(df.get_column_names(), &["a.location", "b.new_deaths"]);
+        assert_eq!(df.height(), 2);
+    }
+
+    fn dataset() -> DataSet {
+        DataSet(df! { "name" => ["a|b", "c"], "value" => [Some(1), None] }.unwrap())
+    }
+
+    #[test]
+    fn dataset_to_csv_should_work() {
+        assert_eq!(dataset().to_csv().unwrap(), "name,value\na|b,1\nc,\n");
+    }
+
+    #[test]
+    fn dataset_to_json_should_work() {
+        let json = dataset().to_json().unwrap();
+        assert_eq!(
+            json,
+            r#"[{"name":"a|b","value":1},{"name":"c","value":null}]"#
+        );
+
+        let ndjson = dataset().to_ndjson().unwrap();
+        assert_eq!(
+            ndjson,
+            "{\"name\":\"a|b\",\"value\":1}\n{\"name\":\"c\",\"value\":null}\n"
+        );
+    }
+
+    #[test]
+    fn dataset_to_markdown_should_work() {
+        let md = dataset().to_markdown().unwrap();
+        assert_eq!(
+            md,
+            "| name | value |\n| --- | --- |\n| a\\|b | 1 |\n| c |  |\n"
+        );
+    }
+
+    #[test]
+    fn dataset_to_parquet_should_work() {
+        let data = dataset().to_parquet().unwrap();
+        let df = ParquetReader::new(std::io::Cursor::new(data))
+            .finish()
+            .unwrap();
+        assert!(df.frame_equal_missing(&dataset()));
+    }
+
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn dataset_to_ipc_should_work() {
+        let data = dataset().to_ipc().unwrap();
+        let df = IpcReader::new(std::io::Cursor::new(data)).finish().unwrap();
+        assert!(df.frame_equal_missing(&dataset()));
     }
 }
@@ -0,0 +1,344 @@
+use std::{io::Cursor, str::FromStr};
+
+use polars::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    decompress::{decompress, member, Compression},
+    error::{invalid, QueryError, Result},
+    fetcher::Content,
+    DataSet,
+};
+
+// Load trait: 未来如果要支持更多的数据格式，只需要实现它即可
+pub trait Load {
+    type Error;
+
+    // 把原始数据加载成 DataSet
+    fn load(self) -> Result<DataSet, Self::Error>;
+}
+
+/// 目前支持的数据格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Format {
+    Csv,
+    Json,
+    NdJson,
+    Parquet,
+}
+
+#[derive(Debug)]
+pub enum Loader {
+    Csv(CsvLoader),
+    Json(JsonLoader),
+    NdJson(NdJsonLoader),
+    Parquet(ParquetLoader),
+}
+
+/// 加载数据时的选项，没有设置的选项使用默认值
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct LoadOptions {
+    /// csv 的分隔符，默认是 ','
+    pub delimiter: Option<char>,
+    /// csv 是否有表头，默认有
+    pub has_header: Option<bool>,
+    /// 推断类型时使用的行数，默认是 16
+    pub infer_schema_length: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+pub struct CsvLoader(pub(crate) Vec<u8>, pub(crate) LoadOptions);
+
+#[derive(Debug, Default)]
+pub struct JsonLoader(pub(crate) Vec<u8>);
+
+#[derive(Debug, Default)]
+pub struct NdJsonLoader(pub(crate) Vec<u8>, pub(crate) LoadOptions);
+
+#[derive(Debug, Default)]
+pub struct ParquetLoader(pub(crate) Vec<u8>);
+
+impl Loader {
+    pub fn new(format: Format, data: Vec<u8>) -> Self {
+        match format {
+            Format::Csv => Loader::Csv(CsvLoader(data, LoadOptions::default())),
+            Format::Json => Loader::Json(JsonLoader(data)),
+            Format::NdJson => Loader::NdJson(NdJsonLoader(data, LoadOptions::default())),
+            Format::Parquet => Loader::Parquet(ParquetLoader(data)),
+        }
+    }
+
+    /// 设置加载选项，对不支持这些选项的格式没有影响
+    pub fn with_options(self, options: LoadOptions) -> Self {
+        match self {
+            Loader::Csv(CsvLoader(data, _)) => Loader::Csv(CsvLoader(data, options)),
+            Loader::NdJson(NdJsonLoader(data, _)) => Loader::NdJson(NdJsonLoader(data, options)),
+            loader => loader,
+        }
+    }
+
+    pub fn load(self) -> Result<DataSet> {
+        match self {
+            Loader::Csv(csv) => csv.load(),
+            Loader::Json(json) => json.load(),
+            Loader::NdJson(ndjson) => ndjson.load(),
+            Loader::Parquet(parquet) => parquet.load(),
+        }
+    }
+}
+
+/// 根据 source 和获取到的内容选择合适的 loader，压缩过的数据会先解压
+///
+/// 优先级：source 中的 `?format=` 参数 > source 的扩展名 > Content-Type，都没有时按 csv 处理
+pub fn detect_content(source: &str, content: Content) -> Result<Loader> {
+    let content = decompress(source, content)?;
+    let format = Format::detect(source, content.content_type.as_deref())?;
+    Ok(Loader::new(format, content.data))
+}
+
+impl Format {
+    /// 按照 `?format=` 参数 > 扩展名 > Content-Type 的顺序推断数据格式，都没有时按 csv 处理
+    pub fn detect(source: &str, content_type: Option<&str>) -> Result<Self> {
+        Ok(match Format::from_hint(source)? {
+            Some(format) => format,
+            None => Format::from_extension(source)
+                .or_else(|| content_type.and_then(Format::from_mime))
+                .unwrap_or(Format::Csv),
+        })
+    }
+
+    /// 从 source 的 `?format=xxx` 参数中获取数据格式
+    pub fn from_hint(source: &str) -> Result<Option<Self>> {
+        let query = match source.split('#').next().and_then(|v| v.split_once('?')) {
+            Some((_, query)) => query,
+            None => return Ok(None),
+        };
+
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(k, _)| *k == "format")
+            .map(|(_, v)| v.parse())
+            .transpose()
+    }
+
+    /// 从 source 的扩展名中推断数据格式
+    ///
+    /// 压缩文件按照去掉压缩扩展名之后的文件名推断，比如 `data.csv.gz`；
+    /// zip 按照 `#` 之后选中的文件推断，比如 `archive.zip#data.csv`
+    pub fn from_extension(source: &str) -> Option<Self> {
+        let path = match member(source) {
+            Some(member) => member,
+            None => source.split('?').next().unwrap_or(source),
+        };
+        let (path, ext) = path.rsplit_once('.')?;
+        match Compression::from_ext(ext) {
+            Some(_) => path.rsplit_once('.')?.1.parse().ok(),
+            None => ext.parse().ok(),
+        }
+    }
+
+    /// 从 Content-Type 中推断数据格式
+    pub fn from_mime(mime: &str) -> Option<Self> {
+        let mime = mime.split(';').next().unwrap_or(mime).trim();
+        match mime {
+            "text/csv" | "application/csv" => Some(Format::Csv),
+            "application/json" | "text/json" => Some(Format::Json),
+            "application/x-ndjson" | "application/jsonl" | "application/x-jsonlines" => {
+                Some(Format::NdJson)
+            }
+            "application/vnd.apache.parquet" | "application/x-parquet" | "application/parquet" => {
+                Some(Format::Parquet)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "ndjson" | "jsonl" => Ok(Format::NdJson),
+            "parquet" | "pq" => Ok(Format::Parquet),
+            v => Err(invalid!("Format {} is not supported", v)),
+        }
+    }
+}
+
+impl TryFrom<String> for Format {
+    type Error = QueryError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl LoadOptions {
+    pub(crate) fn infer_schema_length(&self) -> Option<usize> {
+        Some(self.infer_schema_length.unwrap_or(16))
+    }
+
+    // polars 只支持单字节的分隔符
+    pub(crate) fn delimiter(&self) -> Result<Option<u8>> {
+        match self.delimiter {
+            Some(delimiter) if !delimiter.is_ascii() => {
+                Err(invalid!("Delimiter {} is not supported", delimiter))
+            }
+            delimiter => Ok(delimiter.map(|v| v as u8)),
+        }
+    }
+}
+
+impl Load for CsvLoader {
+    type Error = QueryError;
+
+    fn load(self) -> Result<DataSet, Self::Error> {
+        let options = self.1;
+        let mut reader = CsvReader::new(Cursor::new(self.0))
+            .infer_schema(options.infer_schema_length())
+            .has_header(options.has_header.unwrap_or(true));
+        if let Some(delimiter) = options.delimiter()? {
+            reader = reader.with_delimiter(delimiter);
+        }
+        Ok(DataSet(reader.finish()?))
+    }
+}
+
+impl Load for JsonLoader {
+    type Error = QueryError;
+
+    fn load(self) -> Result<DataSet, Self::Error> {
+        let df = JsonReader::new(Cursor::new(self.0))
+            .with_json_format(JsonFormat::Json)
+            .finish()?;
+        Ok(DataSet(df))
+    }
+}
+
+impl Load for NdJsonLoader {
+    type Error = QueryError;
+
+    fn load(self) -> Result<DataSet, Self::Error> {
+        let df = JsonLineReader::new(Cursor::new(self.0))
+            .infer_schema_len(self.1.infer_schema_length())
+            .finish()?;
+        Ok(DataSet(df))
+    }
+}
+
+impl Load for ParquetLoader {
+    type Error = QueryError;
+
+    fn load(self) -> Result<DataSet, Self::Error> {
+        let df = ParquetReader::new(Cursor::new(self.0)).finish()?;
+        Ok(DataSet(df))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content(data: &[u8], content_type: Option<&str>) -> Content {
+        Content {
+            data: data.to_vec(),
+            content_type: content_type.map(|v| v.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn detect_format_should_work() {
+        assert_eq!(
+            Format::from_extension("https://a.com/b.csv"),
+            Some(Format::Csv)
+        );
+        assert_eq!(
+            Format::from_extension("file:///tmp/b.jsonl?x=1"),
+            Some(Format::NdJson)
+        );
+        assert_eq!(Format::from_extension("https://a.com/data"), None);
+        assert_eq!(
+            Format::from_extension("https://a.com/b.ndjson.gz"),
+            Some(Format::NdJson)
+        );
+        assert_eq!(
+            Format::from_extension("file:///tmp/b.zip#dir/c.parquet"),
+            Some(Format::Parquet)
+        );
+        assert_eq!(Format::from_extension("file:///tmp/b.zip"), None);
+        assert_eq!(
+            Format::from_hint("file:///tmp/b.zip?format=json#c.csv").unwrap(),
+            Some(Format::Json)
+        );
+        assert_eq!(
+            Format::from_hint("https://a.com/b.csv?format=json").unwrap(),
+            Some(Format::Json)
+        );
+        assert!(Format::from_hint("https://a.com/b?format=xls").is_err());
+        assert_eq!(
+            Format::from_mime("application/json; charset=utf-8"),
+            Some(Format::Json)
+        );
+        assert_eq!(Format::from_mime("text/plain"), None);
+    }
+
+    #[test]
+    fn csv_loader_should_work() {
+        let loader =
+            detect_content("https://a.com/data", content(b"a,b\n1,x\n2,y\n", None)).unwrap();
+        let ds = loader.load().unwrap();
+        assert_eq!(ds.shape(), (2, 2));
+    }
+
+    #[test]
+    fn csv_loader_with_options_should_work() {
+        let options = LoadOptions {
+            delimiter: Some(';'),
+            has_header: Some(false),
+            ..Default::default()
+        };
+        let loader = Loader::new(Format::Csv, b"1;x\n2;y\n".to_vec()).with_options(options);
+        let ds = loader.load().unwrap();
+        assert_eq!(ds.shape(), (2, 2));
+        assert_eq!(ds.get_column_names(), &["column_1", "column_2"]);
+    }
+
+    #[test]
+    fn json_loader_should_work() {
+        let data = br#"[{"a": 1, "b": "x"}, {"a": 2, "b": "y"}]"#;
+        let loader = detect_content(
+            "https://a.com/data",
+            content(data, Some("application/json")),
+        )
+        .unwrap();
+        let ds = loader.load().unwrap();
+        assert_eq!(ds.shape(), (2, 2));
+    }
+
+    #[test]
+    fn ndjson_loader_should_work() {
+        let data = b"{\"a\": 1, \"b\": \"x\"}\n{\"a\": 2, \"b\": \"y\"}\n";
+        let loader = detect_content("https://a.com/data.ndjson", content(data, None)).unwrap();
+        let ds = loader.load().unwrap();
+        assert_eq!(ds.shape(), (2, 2));
+    }
+
+    #[test]
+    fn parquet_loader_should_work() {
+        let mut df = df! { "a" => [1, 2], "b" => ["x", "y"] }.unwrap();
+        let mut data = Vec::new();
+        ParquetWriter::new(&mut data).finish(&mut df).unwrap();
+
+        let loader =
+            detect_content("https://a.com/data?format=parquet", content(&data, None)).unwrap();
+        let ds = loader.load().unwrap();
+        assert!(ds.frame_equal(&df));
+    }
+}
@@ -0,0 +1,156 @@
+use std::ops::ControlFlow;
+
+use sqlparser::ast::{visit_expressions_mut, Expr as SqlExpr, Statement, Value as SqlValue};
+
+use crate::error::{invalid, Result};
+
+/// 绑定到 SQL 中 `?` / `$1` 占位符的参数
+///
+/// 参数直接替换解析出来的 AST 中的占位符，不会拼接到 SQL 字符串中，
+/// 所以可以放心地传入用户的输入
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Boolean(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::Boolean(v)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(v: i32) -> Self {
+        Value::Int(v as i64)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Value::Int(v)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::Float(v)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::String(v.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::String(v)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Value::Null, |v| v.into())
+    }
+}
+
+impl From<&Value> for SqlValue {
+    fn from(v: &Value) -> Self {
+        match v {
+            Value::Null => SqlValue::Null,
+            Value::Boolean(b) => SqlValue::Boolean(*b),
+            Value::Int(n) => SqlValue::Number(n.to_string(), false),
+            // 用 Debug 格式保证 2.0 不会变成整数 2
+            Value::Float(n) => SqlValue::Number(format!("{:?}", n), false),
+            Value::String(s) => SqlValue::SingleQuotedString(s.clone()),
+        }
+    }
+}
+
+/// 把 statement 中的占位符替换成对应的参数
+///
+/// `?` 按出现的顺序依次绑定，`$n` 绑定第 n 个参数（从 1 开始）；
+/// 用到的参数个数必须和传入的一致
+pub(crate) fn bind(statement: &mut Statement, params: &[Value]) -> Result<()> {
+    let mut next = 0;
+    let mut used = 0;
+    let flow = visit_expressions_mut(statement, |expr| {
+        let placeholder = match expr {
+            SqlExpr::Value(SqlValue::Placeholder(p)) => p.clone(),
+            _ => return ControlFlow::Continue(()),
+        };
+        let index = match placeholder.as_str() {
+            "?" => {
+                next += 1;
+                next - 1
+            }
+            p => match p.strip_prefix('$').and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) if n > 0 => n - 1,
+                _ => return ControlFlow::Break(invalid!("Placeholder {} is not supported", p)),
+            },
+        };
+        match params.get(index) {
+            Some(value) => {
+                used = used.max(index + 1);
+                *expr = SqlExpr::Value(value.into());
+                ControlFlow::Continue(())
+            }
+            None => ControlFlow::Break(invalid!("No parameter for placeholder {}", placeholder)),
+        }
+    });
+
+    match flow {
+        ControlFlow::Break(e) => Err(e),
+        ControlFlow::Continue(()) if used != params.len() => Err(invalid!(
+            "Expected {} parameter(s), but got {}",
+            used,
+            params.len()
+        )),
+        ControlFlow::Continue(()) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlparser::parser::Parser;
+
+    use super::*;
+    use crate::TryDialect;
+
+    fn bound(sql: &str, params: &[Value]) -> Result<String> {
+        let mut statement = Parser::parse_sql(&TryDialect, sql).unwrap().remove(0);
+        bind(&mut statement, params)?;
+        Ok(statement.to_string())
+    }
+
+    #[test]
+    fn bind_should_work() {
+        let sql = "SELECT a FROM t WHERE b = ? AND c > ? LIMIT ?";
+        let params = ["x' OR 1 = 1".into(), 1.5.into(), 10.into()];
+        assert_eq!(
+            bound(sql, &params).unwrap(),
+            "SELECT a FROM t WHERE b = 'x'' OR 1 = 1' AND c > 1.5 LIMIT 10"
+        );
+
+        let sql = "SELECT a FROM t WHERE b = $2 OR c = $1 OR d = $2";
+        let params = [Value::Null, true.into()];
+        assert_eq!(
+            bound(sql, &params).unwrap(),
+            "SELECT a FROM t WHERE b = true OR c = NULL OR d = true"
+        );
+    }
+
+    #[test]
+    fn bind_should_check_params() {
+        let sql = "SELECT a FROM t WHERE b = ? AND c = ?";
+        assert!(bound(sql, &[1.into()]).is_err());
+        assert!(bound(sql, &[1.into(), 2.into(), 3.into()]).is_err());
+        assert!(bound("SELECT a FROM t WHERE b = $0", &[1.into()]).is_err());
+        assert!(bound("SELECT a FROM t", &[1.into()]).is_err());
+    }
+}
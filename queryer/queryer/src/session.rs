@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+
+use futures::future::try_join_all;
+use polars::prelude::*;
+use sqlparser::{
+    ast::{ObjectName, ObjectType, Query, Statement},
+    parser::Parser,
+};
+
+use crate::{
+    blocking,
+    describe::describe_frame,
+    error::{invalid, node_name, unsupported, Result},
+    params::{self, Value},
+    scan_table,
+    streaming::Spool,
+    Catalog, DataSet, Sql, TryDialect,
+};
+
+/// 一次交互式会话：除了 catalog 中注册的表，还保存了会话中用 CREATE VIEW 创建的视图
+///
+/// 视图只是组合好的 LazyFrame，数据源在创建视图时读取，查询视图时才真正执行
+#[derive(Default)]
+pub struct Session {
+    catalog: Catalog,
+    views: HashMap<String, LazyFrame>,
+    // 流式执行时下载的数据源，没有开启流式执行时为 None
+    spool: Option<Spool>,
+    // FROM 中可以直接使用的 url scheme，None 时不限制
+    schemes: Option<Vec<String>>,
+    // 查询最多返回的行数，None 时不限制
+    max_rows: Option<usize>,
+}
+
+impl Session {
+    pub fn new(catalog: Catalog) -> Self {
+        Self {
+            catalog,
+            views: HashMap::new(),
+            spool: None,
+            schemes: None,
+            max_rows: None,
+        }
+    }
+
+    /// 开启流式执行，用于处理比内存大的数据
+    ///
+    /// http / https 数据源边下载边写入临时文件，本地文件直接读取，查询用 polars 的 streaming 引擎执行，
+    /// 只有过滤、投影、聚合等支持流式处理的部分才能控制内存。开启后不使用磁盘缓存，
+    /// 下载的临时文件在会话结束时删除
+    pub fn with_streaming(mut self, streaming: bool) -> Self {
+        self.spool = streaming.then(Spool::default);
+        self
+    }
+
+    /// 限制 FROM 中可以直接使用的 url，只有 scheme 在 schemes 中的 url 才能读取
+    ///
+    /// catalog 中注册的表不受限制。对外提供服务时可以传入空的列表，只允许查询 catalog 中的表，
+    /// 避免客户端读取服务器上的文件（`file://`）或者让服务器去请求任意的地址
+    pub fn with_allowed_schemes<I, S>(mut self, schemes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.schemes = Some(schemes.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// 限制查询最多返回的行数，限制会加到执行计划的最后，超出的行不会被计算出来
+    ///
+    /// 只对查询生效，DESCRIBE 和 EXPLAIN 不受影响
+    pub fn with_max_rows(mut self, max_rows: usize) -> Self {
+        self.max_rows = Some(max_rows);
+        self
+    }
+
+    pub fn catalog(&self) -> &Catalog {
+        &self.catalog
+    }
+
+    /// 会话中创建的所有视图，按字母顺序排列
+    pub fn views(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.views.keys().map(|v| v.as_str()).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// 执行一条 sql：查询、DESCRIBE、EXPLAIN、CREATE VIEW 或者 DROP VIEW
+    ///
+    /// EXPLAIN 返回只有一列 plan 的 DataSet，每行是执行计划中的一行；
+    /// CREATE VIEW 和 DROP VIEW 返回空的 DataSet
+    pub async fn query<T: AsRef<str>>(&mut self, sql: T) -> Result<DataSet> {
+        self.query_with_params(sql, &[]).await
+    }
+
+    /// 和 query 一样，但先把 SQL 中的 `?` / `$1` 占位符替换成 params 中对应的参数
+    pub async fn query_with_params<T: AsRef<str>>(
+        &mut self,
+        sql: T,
+        params: &[Value],
+    ) -> Result<DataSet> {
+        let mut ast = Parser::parse_sql(&TryDialect, sql.as_ref())?;
+
+        if ast.len() != 1 {
+            return Err(invalid!("Only support single sql at the moment"));
+        }
+        params::bind(&mut ast[0], params)?;
+
+        match &ast[0] {
+            Statement::Query(q) => {
+                let mut df = self.plan(q).await?;
+                if let Some(max_rows) = self.max_rows {
+                    df = df.limit(IdxSize::try_from(max_rows).unwrap_or(IdxSize::MAX));
+                }
+                Ok(DataSet(self.collect(df).await?))
+            }
+            // DESCRIBE <url> 返回数据的结构而不是数据本身
+            Statement::ExplainTable { table_name, .. } => {
+                let df = self.collect(self.scan(name(table_name)).await?).await?;
+                Ok(DataSet(describe_frame(&df)?))
+            }
+            Statement::Explain {
+                analyze: false,
+                verbose,
+                statement,
+                ..
+            } => match statement.as_ref() {
+                Statement::Query(q) => Ok(DataSet(self.explain(q, *verbose).await?)),
+                v => Err(unsupported!(
+                    node_name("Statement", v),
+                    "Only support EXPLAIN <query>"
+                )),
+            },
+            Statement::CreateView {
+                or_replace,
+                materialized,
+                name: view,
+                columns,
+                query,
+                ..
+            } => {
+                if *materialized || !columns.is_empty() {
+                    return Err(unsupported!(
+                        "Statement::CreateView",
+                        "Only support CREATE VIEW <name> AS <query>"
+                    ));
+                }
+                let view = name(view);
+                if !or_replace && self.views.contains_key(view) {
+                    return Err(invalid!("View {} already exists", view));
+                }
+                let df = self.plan(query).await?;
+                self.views.insert(view.to_owned(), df);
+                Ok(DataSet(DataFrame::empty()))
+            }
+            Statement::Drop {
+                object_type: ObjectType::View,
+                if_exists,
+                names,
+                ..
+            } => {
+                for view in names.iter().map(name) {
+                    if self.views.remove(view).is_none() && !if_exists {
+                        return Err(invalid!("View {} does not exist", view));
+                    }
+                }
+                Ok(DataSet(DataFrame::empty()))
+            }
+            v => Err(unsupported!(
+                node_name("Statement", v),
+                "We only support Query at the moment"
+            )),
+        }
+    }
+
+    // 读取 query 中用到的所有数据源，生成执行计划
+    async fn plan(&self, query: &Query) -> Result<LazyFrame> {
+        let sql: Sql = query.try_into()?;
+
+        // 视图之外的数据源，并发地读入 DataFrame
+        let sources: Vec<_> = sql
+            .external_sources()
+            .into_iter()
+            .filter(|source| !self.views.contains_key(*source))
+            .collect();
+        let frames = try_join_all(sources.iter().map(|source| self.scan(source))).await?;
+
+        let mut tables = self.views.clone();
+        tables.extend(sources.into_iter().map(|v| v.to_owned()).zip(frames));
+        sql.build(&tables)
+    }
+
+    // SQL 被转换成的各个子句以及 polars 优化后的执行计划，verbose 时还包括优化前的
+    async fn explain(&self, query: &Query, verbose: bool) -> Result<DataFrame> {
+        let sql: Sql = query.try_into()?;
+        let df = self.plan(query).await?;
+
+        let mut lines = vec!["== SQL ==".to_owned()];
+        lines.extend(sql.to_string().lines().map(|v| v.to_owned()));
+        if verbose {
+            lines.push("== Logical Plan ==".to_owned());
+            lines.extend(df.describe_plan().lines().map(|v| v.to_owned()));
+        }
+        lines.push("== Optimized Logical Plan ==".to_owned());
+        lines.extend(df.describe_optimized_plan()?.lines().map(|v| v.to_owned()));
+        Ok(df! { "plan" => lines }?)
+    }
+
+    /// 读取表名对应的数据，依次在视图、catalog 中查找，都没有时当作 url
+    pub async fn scan(&self, name: &str) -> Result<LazyFrame> {
+        if let Some(df) = self.views.get(name) {
+            return Ok(df.clone());
+        }
+        let table = match self.catalog.get(name) {
+            Some(table) => table.clone(),
+            None => {
+                self.check_url(name)?;
+                self.catalog.resolve(name)?
+            }
+        };
+        match &self.spool {
+            Some(spool) => spool.scan(&table).await,
+            None => scan_table(&table).await,
+        }
+    }
+
+    // 没有注册过的表名当作 url 读取之前，确认 url 的 scheme 是允许的
+    fn check_url(&self, name: &str) -> Result<()> {
+        let schemes = match &self.schemes {
+            Some(schemes) => schemes,
+            None => return Ok(()),
+        };
+        match name.split_once("://") {
+            Some((scheme, _)) if schemes.iter().any(|s| s.eq_ignore_ascii_case(scheme)) => Ok(()),
+            Some(_) => Err(invalid!(
+                "Reading {} directly is not allowed, only tables in catalog can be queried",
+                name
+            )),
+            None => Err(invalid!("Table {} is not found in catalog", name)),
+        }
+    }
+
+    // polars 的计算会占用线程很久，放到阻塞线程池中执行
+    async fn collect(&self, df: LazyFrame) -> Result<DataFrame> {
+        let df = df.with_streaming(self.spool.is_some());
+        blocking(move || Ok(df.collect()?)).await
+    }
+}
+
+fn name(name: &ObjectName) -> &str {
+    &name.0.first().unwrap().value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{QueryError, TableSource};
+
+    fn session() -> Session {
+        let url = format!("file://{}/fixtures/covid.csv", env!("CARGO_MANIFEST_DIR"));
+        Session::new(Catalog::new().with_table("covid", TableSource::new(url)))
+    }
+
+    #[tokio::test]
+    async fn cte_should_work() {
+        let mut session = session();
+        let sql =
+            "WITH europe AS (SELECT location, new_cases FROM covid WHERE continent = 'Europe'), \
+            top AS (SELECT location FROM europe ORDER BY new_cases DESC LIMIT 2) \
+            SELECT location FROM top ORDER BY location";
+        let df = session.query(sql).await.unwrap();
+        assert_eq!(df.height(), 2);
+        assert_eq!(df["location"].get(0).unwrap(), AnyValue::Utf8("Italy"));
+    }
+
+    #[tokio::test]
+    async fn explain_should_work() {
+        let mut session = session();
+        let sql = "EXPLAIN SELECT location FROM covid WHERE new_cases > 10000 ORDER BY location";
+        let df = session.query(sql).await.unwrap();
+        let plan: Vec<_> = df["plan"].utf8().unwrap().into_no_null_iter().collect();
+        assert_eq!(plan[0], "== SQL ==");
+        assert_eq!(plan[1], "FROM covid");
+        assert!(plan.contains(&"== Optimized Logical Plan =="));
+        assert!(!plan.contains(&"== Logical Plan =="));
+
+        let df = session
+            .query(sql.replace("EXPLAIN", "EXPLAIN VERBOSE"))
+            .await;
+        assert!(df.unwrap().height() > plan.len());
+        assert!(session
+            .query("EXPLAIN ANALYZE SELECT * FROM covid")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn streaming_should_work() {
+        let mut session = session().with_streaming(true);
+        let sql = "SELECT continent, SUM(new_cases) AS cases FROM covid \
+            WHERE new_cases > 0 GROUP BY continent ORDER BY cases DESC";
+        let streamed = session.query(sql).await.unwrap();
+        let expected = self::session().query(sql).await.unwrap();
+        assert!(streamed.frame_equal_missing(&expected));
+
+        session
+            .query("CREATE VIEW europe AS SELECT * FROM covid WHERE continent = 'Europe'")
+            .await
+            .unwrap();
+        let df = session.query("SELECT location FROM europe").await;
+        assert_eq!(df.unwrap().height(), 4);
+    }
+
+    #[tokio::test]
+    async fn compressed_sources_should_work() {
+        use std::io::Write;
+
+        let csv = std::fs::read(format!("{}/fixtures/covid.csv", env!("CARGO_MANIFEST_DIR")));
+        let csv = csv.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+
+        let gz = dir.path().join("covid.csv.gz");
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(&csv).unwrap();
+        std::fs::write(&gz, encoder.finish().unwrap()).unwrap();
+
+        let archive = dir.path().join("archive.zip");
+        let mut writer = ::zip::ZipWriter::new(std::fs::File::create(&archive).unwrap());
+        for name in ["readme.txt", "data/covid.csv"] {
+            writer.start_file(name, Default::default()).unwrap();
+            writer.write_all(&csv).unwrap();
+        }
+        writer.finish().unwrap();
+
+        for streaming in [false, true] {
+            let mut session = Session::default().with_streaming(streaming);
+            for source in [
+                format!("file://{}", gz.display()),
+                format!("file://{}#data/covid.csv", archive.display()),
+            ] {
+                let sql = format!(
+                    "SELECT location FROM {} WHERE continent = 'Europe' ORDER BY location",
+                    source
+                );
+                let df = session.query(sql).await.unwrap();
+                assert_eq!(df.height(), 4);
+                assert_eq!(df["location"].get(0).unwrap(), AnyValue::Utf8("France"));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn allowed_schemes_should_restrict_urls() {
+        let url = format!("file://{}/fixtures/covid.csv", env!("CARGO_MANIFEST_DIR"));
+        let sql = format!("SELECT location FROM {}", url);
+
+        // catalog 中的表不受限制，直接使用的 url 需要 scheme 被允许
+        let mut restricted = session().with_allowed_schemes(Vec::<String>::new());
+        let df = restricted.query("SELECT location FROM covid").await;
+        assert_eq!(df.unwrap().height(), 12);
+        assert!(restricted.query(&sql).await.is_err());
+        assert!(restricted.query(format!("DESCRIBE {}", url)).await.is_err());
+
+        let mut http_only = session().with_allowed_schemes(["http", "https"]);
+        let df = http_only.query(&sql).await;
+        assert!(matches!(df, Err(QueryError::Invalid(_))));
+        let mut files = session().with_allowed_schemes(["file"]);
+        assert_eq!(files.query(&sql).await.unwrap().height(), 12);
+    }
+
+    #[tokio::test]
+    async fn max_rows_should_limit_queries() {
+        let mut limited = session().with_max_rows(5);
+        let df = limited.query("SELECT location FROM covid").await.unwrap();
+        assert_eq!(df.height(), 5);
+        let df = limited.query("SELECT location FROM covid LIMIT 3").await;
+        assert_eq!(df.unwrap().height(), 3);
+
+        // 限制加在排序之后，返回的是排序后的前几行
+        let sql = "SELECT location FROM covid ORDER BY total_cases DESC";
+        let all = session().query(sql).await.unwrap();
+        let df = limited.query(sql).await.unwrap();
+        assert!(df.frame_equal_missing(&all.head(Some(5))));
+        let df = limited.query("DESCRIBE covid").await.unwrap();
+        assert!(df.frame_equal_missing(&session().query("DESCRIBE covid").await.unwrap()));
+    }
+
+    #[tokio::test]
+    async fn view_should_work() {
+        let mut session = session();
+        let sql =
+            "CREATE VIEW asia AS SELECT location, new_deaths FROM covid WHERE continent = 'Asia'";
+        assert_eq!(session.query(sql).await.unwrap().height(), 0);
+        assert!(session.query(sql).await.is_err());
+        assert_eq!(session.views(), ["asia"]);
+
+        let sql = "CREATE OR REPLACE VIEW asia AS SELECT * FROM asia WHERE new_deaths > 0";
+        session.query(sql).await.unwrap();
+        let all = session.query("SELECT * FROM asia").await.unwrap();
+        let df = session
+            .query("SELECT location FROM asia ORDER BY new_deaths DESC LIMIT 1")
+            .await
+            .unwrap();
+        assert!(all.height() > 1);
+        assert_eq!(df.height(), 1);
+        assert_eq!(session.query("DESCRIBE asia").await.unwrap().height(), 2);
+
+        session.query("DROP VIEW asia").await.unwrap();
+        assert!(session.query("DROP VIEW asia").await.is_err());
+        session.query("DROP VIEW IF EXISTS asia").await.unwrap();
+        assert!(session.query("SELECT * FROM asia").await.is_err());
+    }
+}
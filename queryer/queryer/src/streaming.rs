@@ -0,0 +1,172 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use polars::prelude::*;
+use tempfile::TempPath;
+use tokio::fs;
+
+use crate::{
+    catalog::TableSource,
+    decompress::{decompress_file, Compression},
+    error::{invalid, Result},
+    fetcher::{download, file_path, Content},
+    loader::{Format, Loader},
+};
+
+/// 流式执行时下载到本地的数据源
+///
+/// http / https 的响应边下载边写入临时文件，之后用 polars 的 scan_xxx 按需读取，
+/// 数据不需要一次性读入内存；压缩过的数据也是边解压边写入临时文件。
+/// 同一个 url 在会话中只下载一次，临时文件在 Spool 被 drop 时删除
+#[derive(Default)]
+pub(crate) struct Spool {
+    // url -> (临时文件, Content-Type)
+    files: Mutex<HashMap<String, (TempPath, Option<String>)>>,
+}
+
+impl Spool {
+    /// 获取 table 对应数据的 LazyFrame，没有压缩的本地文件直接读取，不需要复制
+    pub(crate) async fn scan(&self, table: &TableSource) -> Result<LazyFrame> {
+        let url = table.url.as_str();
+        if let Some((path, content_type)) = self.files.lock().unwrap().get(url) {
+            return scan_file(table, path, content_type.as_deref());
+        }
+
+        let (downloaded, content) = match url.split_once("://") {
+            Some(("file", _)) => (None, Content::default()),
+            Some(("http" | "https", _)) => {
+                let (file, path) = tempfile::Builder::new()
+                    .prefix("queryer-")
+                    .tempfile()?
+                    .into_parts();
+                let content = download(url, &mut fs::File::from_std(file)).await?;
+                (Some(path), content)
+            }
+            _ => return Err(invalid!("We only support http/https/file at the moment")),
+        };
+
+        let compression = Compression::detect(url, content.content_encoding.as_deref());
+        let path = match (downloaded, compression) {
+            (None, None) => return scan_file(table, Path::new(file_path(url)), None),
+            (None, Some(compression)) => {
+                unpack(compression, url, PathBuf::from(file_path(url))).await?
+            }
+            // 下载的压缩文件解压之后就不再需要了
+            (Some(path), Some(compression)) => unpack(compression, url, path).await?,
+            (Some(path), None) => path,
+        };
+        let content_type = content.content_type;
+        let df = scan_file(table, &path, content_type.as_deref())?;
+        self.files
+            .lock()
+            .unwrap()
+            .insert(url.to_owned(), (path, content_type));
+        Ok(df)
+    }
+}
+
+// 解压是同步的文件读写，放到 tokio 的阻塞线程池中执行，不阻塞异步任务
+async fn unpack<P>(compression: Compression, url: &str, path: P) -> Result<TempPath>
+where
+    P: AsRef<Path> + Send + 'static,
+{
+    let url = url.to_owned();
+    tokio::task::spawn_blocking(move || decompress_file(compression, &url, path.as_ref()))
+        .await
+        .map_err(io::Error::from)?
+}
+
+// 按照 table 中的格式和选项读取本地文件，格式的推断和 Loader 一致
+fn scan_file(table: &TableSource, path: &Path, content_type: Option<&str>) -> Result<LazyFrame> {
+    let format = match table.format {
+        Some(format) => format,
+        None => Format::detect(&table.url, content_type)?,
+    };
+    let options = &table.options;
+    let df = match format {
+        Format::Csv => {
+            let mut reader = LazyCsvReader::new(path)
+                .with_infer_schema_length(options.infer_schema_length())
+                .has_header(options.has_header.unwrap_or(true));
+            if let Some(delimiter) = options.delimiter()? {
+                reader = reader.with_delimiter(delimiter);
+            }
+            reader.finish()?
+        }
+        Format::NdJson => LazyJsonLineReader::new(path)
+            .with_infer_schema_length(options.infer_schema_length())
+            .finish()?,
+        Format::Parquet => LazyFrame::scan_parquet(path, ScanArgsParquet::default())?,
+        // json 数组没法按需读取，只能整个加载到内存中
+        Format::Json => Loader::new(format, std::fs::read(path)?).load()?.0.lazy(),
+    };
+    Ok(df)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    // 返回 ndjson 的 http server，url 中没有扩展名，只能根据 Content-Type 推断格式
+    async fn serve() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let body = "{\"a\": 1, \"b\": \"x\"}\n{\"a\": 2, \"b\": \"y\"}\n";
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let _ = socket.read(&mut buf).await.unwrap();
+                let resp = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\n\
+                    Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                socket.write_all(resp.as_bytes()).await.unwrap();
+            }
+        });
+        format!("http://{}/data", addr)
+    }
+
+    #[tokio::test]
+    async fn spool_should_download_to_temp_file() {
+        let url = serve().await;
+        let spool = Spool::default();
+        let table = TableSource::new(url.as_str());
+
+        let df = spool.scan(&table).await.unwrap();
+        let df = df.filter(col("a").gt(lit(1))).collect().unwrap();
+        assert_eq!(df.shape(), (1, 2));
+
+        let path = spool.files.lock().unwrap()[&url].0.to_path_buf();
+        assert!(path.exists());
+        drop(spool);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn spool_should_scan_local_file() {
+        let url = format!("file://{}/fixtures/covid.csv", env!("CARGO_MANIFEST_DIR"));
+        let spool = Spool::default();
+        let df = spool
+            .scan(&TableSource::new(url))
+            .await
+            .unwrap()
+            .select([col("location")])
+            .with_streaming(true)
+            .collect()
+            .unwrap();
+        assert_eq!(df.shape(), (12, 1));
+        assert!(spool.files.lock().unwrap().is_empty());
+    }
+}